@@ -0,0 +1,52 @@
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use toggl_rs::cache::MetadataCache;
+use toggl_rs::{Toggl, TogglError};
+
+/// Cached metadata is used by read-only commands without asking the api if it is younger than this
+const CACHE_MAX_AGE_MINUTES: i64 = 60;
+
+/// The cache file for the given token under the XDG cache dir.
+/// The file name depends on the token so different accounts do not share a cache.
+/// It uses a stable hash so the cache survives updates of the compiler.
+fn cache_path(api_token: &str) -> Option<PathBuf> {
+    let hash = Sha256::digest(api_token.trim_end().as_bytes());
+    dirs::cache_dir().map(|d| {
+        d.join("toggl")
+            .join(format!("metadata-{}.json", hex::encode(&hash[..8])))
+    })
+}
+
+/// Creates the toggl object from the on-disk cache if possible.
+/// Read-only commands use a recent cache as is, otherwise only the changes since the cache was written are fetched.
+pub fn connect(api_token: &str, read_only: bool) -> Result<Toggl, TogglError> {
+    let cache = cache_path(api_token).and_then(|p| MetadataCache::load(&p).unwrap_or(None));
+
+    let toggl = match cache {
+        Some(c) => {
+            let fresh = read_only && c.age() < chrono::Duration::minutes(CACHE_MAX_AGE_MINUTES);
+            let mut t = Toggl::from_cache(api_token, c);
+            if fresh {
+                return Ok(t);
+            }
+            if let Err(e) = t.revalidate() {
+                eprintln!("Could not update cached data, using it anyway: {}", e);
+                return Ok(t);
+            }
+            t
+        }
+        None => Toggl::init(api_token)?,
+    };
+
+    store(api_token, &toggl);
+    Ok(toggl)
+}
+
+/// Writes the cache, e.g., again at the end of a run so projects fetched by id are kept.
+pub fn store(api_token: &str, toggl: &Toggl) {
+    if let Some(p) = cache_path(api_token) {
+        if let Err(e) = toggl.to_cache().store(&p) {
+            eprintln!("Could not write cache: {}", e);
+        }
+    }
+}
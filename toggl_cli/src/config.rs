@@ -0,0 +1,368 @@
+use ansi_term::Colour;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A column of the entry table.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Index,
+    Date,
+    Start,
+    Stop,
+    Description,
+    Project,
+    Tags,
+    Billable,
+    Duration,
+}
+
+impl Column {
+    fn parse(s: &str) -> Result<Column, String> {
+        toml::Value::String(s.trim().to_owned())
+            .try_into()
+            .map_err(|_| format!("Unknown column {}", s))
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Index => "#",
+            Column::Date => "Date",
+            Column::Start => "Start",
+            Column::Stop => "Stop",
+            Column::Description => "Description",
+            Column::Project => "Project",
+            Column::Tags => "Tags",
+            Column::Billable => "$",
+            Column::Duration => "Duration",
+        }
+    }
+}
+
+/// The config file as written by the user, every setting is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Name or id of the workspace entries without a project are started in
+    pub workspace: Option<String>,
+    /// Name of the project entries are started in if none is given
+    pub project: Option<String>,
+    /// Tags of started entries
+    pub tags: Option<Vec<String>>,
+    /// `local`, a name like `Europe/Berlin` or an offset like `+02:00`
+    pub timezone: Option<String>,
+    pub week_start: Option<String>,
+    pub date_format: Option<String>,
+    pub time_format: Option<String>,
+    pub color: Option<bool>,
+    /// Displayed durations are rounded to this many minutes
+    pub rounding: Option<u32>,
+    pub columns: Option<Vec<Column>>,
+    /// Settings of named profiles, they override the settings above
+    pub profiles: BTreeMap<String, ConfigFile>,
+}
+
+impl ConfigFile {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("toggl").join("config.toml"))
+    }
+
+    /// Reads the config, a missing file is an empty config.
+    pub fn load(path: &Path) -> Result<ConfigFile, String> {
+        match fs::read_to_string(path) {
+            Ok(s) => {
+                toml::from_str(&s).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(e) => Err(format!("Could not read config {}: {}", path.display(), e)),
+        }
+    }
+
+    /// The settings with the ones of the profile applied.
+    pub fn for_profile(mut self, profile: &str) -> Result<ConfigFile, String> {
+        let p = match self.profiles.remove(profile) {
+            Some(p) => p,
+            None => return Ok(self),
+        };
+        if !p.profiles.is_empty() {
+            return Err(format!("Profile {} can not contain profiles", profile));
+        }
+        Ok(ConfigFile {
+            workspace: p.workspace.or(self.workspace),
+            project: p.project.or(self.project),
+            tags: p.tags.or(self.tags),
+            timezone: p.timezone.or(self.timezone),
+            week_start: p.week_start.or(self.week_start),
+            date_format: p.date_format.or(self.date_format),
+            time_format: p.time_format.or(self.time_format),
+            color: p.color.or(self.color),
+            rounding: p.rounding.or(self.rounding),
+            columns: p.columns.or(self.columns),
+            profiles: BTreeMap::new(),
+        })
+    }
+}
+
+/// The timezone times are shown in.
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Local,
+    Named(chrono_tz::Tz),
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    fn parse(s: &str) -> Result<Zone, String> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if let Ok(tz) = s.parse::<chrono_tz::Tz>() {
+            return Ok(Zone::Named(tz));
+        }
+        DateTime::parse_from_rfc3339(&format!("2000-01-01T00:00:00{}", s))
+            .map(|d| Zone::Fixed(*d.offset()))
+            .map_err(|_| format!("Unknown timezone {}", s))
+    }
+
+    pub fn convert(self, t: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Local => t.with_timezone(&chrono::Local).fixed_offset(),
+            Zone::Named(tz) => t.with_timezone(&tz).fixed_offset(),
+            Zone::Fixed(o) => t.with_timezone(&o),
+        }
+    }
+
+    /// The start of the day in this timezone.
+    /// If midnight does not exist because of a daylight saving time change, the day starts at the end of the gap.
+    pub fn midnight(self, date: NaiveDate) -> DateTime<Utc> {
+        fn midnight<Tz: TimeZone>(tz: &Tz, t: NaiveDateTime) -> DateTime<Utc> {
+            if let Some(v) = tz.from_local_datetime(&t).earliest() {
+                return v.with_timezone(&Utc);
+            }
+            // the first instant whose local time is not before midnight, offsets are less than a day
+            let before = |s: i64| {
+                DateTime::from_timestamp(s, 0)
+                    .map_or(s < 0, |v| v.with_timezone(tz).naive_local() < t)
+            };
+            let (mut lo, mut hi) = (
+                t.and_utc().timestamp() - 86400,
+                t.and_utc().timestamp() + 86400,
+            );
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if before(mid) {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            DateTime::from_timestamp(lo, 0).unwrap_or_else(|| t.and_utc())
+        }
+        let t = date.and_time(NaiveTime::MIN);
+        match self {
+            Zone::Local => midnight(&chrono::Local, t),
+            Zone::Named(tz) => midnight(&tz, t),
+            Zone::Fixed(o) => midnight(&o, t),
+        }
+    }
+
+    /// The time of the day in this timezone, an error if the time is skipped by a daylight saving time change.
+    /// Ambiguous times are read as the earlier one.
+    pub fn at(self, date: NaiveDate, time: NaiveTime) -> Result<DateTime<Utc>, String> {
+        fn at<Tz: TimeZone>(tz: &Tz, t: NaiveDateTime) -> Option<DateTime<Utc>> {
+            tz.from_local_datetime(&t)
+                .earliest()
+                .map(|v| v.with_timezone(&Utc))
+        }
+        let t = date.and_time(time);
+        match self {
+            Zone::Local => at(&chrono::Local, t),
+            Zone::Named(tz) => at(&tz, t),
+            Zone::Fixed(o) => at(&o, t),
+        }
+        .ok_or_else(|| format!("The time {} does not exist in {}", t, self))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Zone::Local => write!(f, "the local timezone"),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+            Zone::Fixed(o) => write!(f, "{}", o),
+        }
+    }
+}
+
+/// The settings of a run, the config file overridden by flags.
+#[derive(Debug)]
+pub struct Settings {
+    pub workspace: Option<String>,
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+    pub zone: Zone,
+    pub week_start: chrono::Weekday,
+    pub date_format: String,
+    pub time_format: String,
+    pub color: bool,
+    pub rounding: u32,
+    pub columns: Option<Vec<Column>>,
+}
+
+impl Settings {
+    pub fn new(file: ConfigFile, matches: &ArgMatches) -> Result<Settings, String> {
+        let flag = |name: &str| matches.value_of(name).map(|v| v.to_owned());
+        let zone = match flag("timezone").or(file.timezone) {
+            Some(v) => Zone::parse(&v)?,
+            None => Zone::Local,
+        };
+        let week_start = match flag("week_start").or(file.week_start) {
+            Some(v) => v
+                .parse::<chrono::Weekday>()
+                .map_err(|_| format!("Unknown weekday {}", v))?,
+            None => chrono::Weekday::Mon,
+        };
+        let rounding = match flag("rounding") {
+            Some(v) => v
+                .parse()
+                .map_err(|_| format!("Rounding has to be minutes, not {}", v))?,
+            None => file.rounding.unwrap_or(0),
+        };
+        let columns = match flag("columns") {
+            Some(v) => Some(
+                v.split(',')
+                    .map(Column::parse)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => file.columns,
+        };
+        let color = if matches.is_present("no_color") {
+            false
+        } else if matches.is_present("color") {
+            true
+        } else {
+            file.color.unwrap_or(true)
+        };
+        Ok(Settings {
+            workspace: flag("workspace").or(file.workspace),
+            project: file.project,
+            tags: file.tags.unwrap_or_default(),
+            zone,
+            week_start,
+            date_format: flag("date_format")
+                .or(file.date_format)
+                .unwrap_or_else(|| "%Y-%m-%d".to_owned()),
+            time_format: flag("time_format")
+                .or(file.time_format)
+                .unwrap_or_else(|| "%H:%M".to_owned()),
+            color,
+            rounding,
+            columns,
+        })
+    }
+
+    pub fn format_time(&self, t: DateTime<Utc>) -> String {
+        self.zone.convert(t).format(&self.time_format).to_string()
+    }
+
+    pub fn format_date(&self, t: DateTime<Utc>) -> String {
+        self.zone.convert(t).format(&self.date_format).to_string()
+    }
+
+    /// Rounds to the nearest multiple of the rounding.
+    pub fn round(&self, d: chrono::Duration) -> chrono::Duration {
+        if self.rounding == 0 {
+            return d;
+        }
+        let step = i64::from(self.rounding) * 60;
+        let secs = (d.num_seconds() + step / 2) / step * step;
+        chrono::Duration::seconds(secs)
+    }
+
+    pub fn paint(&self, c: Colour, text: &str) -> String {
+        if self.color {
+            c.paint(text).to_string()
+        } else {
+            text.to_owned()
+        }
+    }
+
+    pub fn start_of_day(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.zone.midnight(self.zone.convert(now).date_naive())
+    }
+
+    pub fn start_of_week(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = self.zone.convert(now).date_naive();
+        let days = (7 + today.weekday().num_days_from_monday()
+            - self.week_start.num_days_from_monday())
+            % 7;
+        self.zone
+            .midnight(today - chrono::Duration::days(i64::from(days)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn at_local_time() {
+        let berlin = Zone::Named(chrono_tz::Europe::Berlin);
+        let t = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+        assert_eq!(
+            berlin.at(day(2020, 3, 2), t),
+            Ok(utc("2020-03-02T08:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn at_skipped_time_is_an_error() {
+        let berlin = Zone::Named(chrono_tz::Europe::Berlin);
+        let t = NaiveTime::from_hms_opt(2, 30, 0).unwrap();
+        let e = berlin.at(day(2020, 3, 29), t).unwrap_err();
+        assert!(e.contains("does not exist in Europe/Berlin"), "{}", e);
+    }
+
+    #[test]
+    fn at_ambiguous_time_is_the_earlier() {
+        let berlin = Zone::Named(chrono_tz::Europe::Berlin);
+        let t = NaiveTime::from_hms_opt(2, 30, 0).unwrap();
+        assert_eq!(
+            berlin.at(day(2020, 10, 25), t),
+            Ok(utc("2020-10-25T00:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn midnight_in_a_gap_is_the_end_of_the_gap() {
+        // clocks jumped from 00:00 to 01:00 in Sao Paulo
+        let sao_paulo = Zone::Named(chrono_tz::America::Sao_Paulo);
+        assert_eq!(
+            sao_paulo.midnight(day(2018, 11, 4)),
+            utc("2018-11-04T03:00:00Z")
+        );
+        assert_eq!(
+            sao_paulo.midnight(day(2018, 11, 5)),
+            utc("2018-11-05T02:00:00Z")
+        );
+    }
+
+    #[test]
+    fn midnight_of_a_fixed_offset() {
+        let zone = Zone::Fixed(FixedOffset::east_opt(-5 * 3600).unwrap());
+        assert_eq!(zone.midnight(day(2020, 3, 2)), utc("2020-03-02T05:00:00Z"));
+    }
+}
@@ -0,0 +1,322 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::profile;
+
+/// Takes precedence over the stored token of the default profile.
+/// Named profiles use `TOGGL_API_TOKEN_<NAME>` instead, so their journal and settings are never sent to another account.
+const ENV_VAR: &str = "TOGGL_API_TOKEN";
+
+/// The environment variable with the token of the profile, e.g., `TOGGL_API_TOKEN_WORK` for `work`.
+fn env_var(profile: &str) -> String {
+    if profile == profile::DEFAULT {
+        ENV_VAR.to_owned()
+    } else {
+        format!("{}_{}", ENV_VAR, profile.to_uppercase().replace('-', "_"))
+    }
+}
+
+#[cfg(not(test))]
+const PBKDF2_ROUNDS: u32 = 200_000;
+/// The key derivation is slow on purpose, tests use fewer rounds
+#[cfg(test)]
+const PBKDF2_ROUNDS: u32 = 1_000;
+const SALT_LEN: usize = 16;
+/// Marks the format of the encrypted token file
+const ENCRYPTED_VERSION: &str = "v1";
+
+/// The directory of the token files of the profile, the default profile uses the config directory itself.
+fn profile_dir(profile: &str) -> Option<PathBuf> {
+    let dir = dirs::config_dir()?.join("toggl");
+    if profile == profile::DEFAULT {
+        Some(dir)
+    } else {
+        Some(dir.join("profiles").join(profile))
+    }
+}
+
+/// The token in plain text, only readable by the user.
+fn token_path(profile: &str) -> Option<PathBuf> {
+    profile_dir(profile).map(|d| d.join("api_token"))
+}
+
+/// The token encrypted with a passphrase.
+fn encrypted_token_path(profile: &str) -> Option<PathBuf> {
+    profile_dir(profile).map(|d| d.join("api_token.enc"))
+}
+
+/// How the token of a profile is stored.
+pub enum Stored {
+    Plain,
+    Encrypted,
+    Missing,
+}
+
+pub fn stored(profile: &str) -> Stored {
+    if token_path(profile).is_some_and(|p| p.exists()) {
+        Stored::Plain
+    } else if encrypted_token_path(profile).is_some_and(|p| p.exists()) {
+        Stored::Encrypted
+    } else {
+        Stored::Missing
+    }
+}
+
+/// Finds the api token in the environment variable, the token file or the encrypted token file of the profile, in this order.
+/// Asks for the passphrase if only the encrypted file exists.
+pub fn resolve(profile: &str) -> Result<String, String> {
+    if let Ok(token) = std::env::var(env_var(profile)) {
+        if !token.trim().is_empty() {
+            return Ok(token.trim().to_owned());
+        }
+    }
+    if let Some(p) = token_path(profile).filter(|p| p.exists()) {
+        check_permissions(&p)?;
+        let token =
+            fs::read_to_string(&p).map_err(|e| format!("Could not read {}: {}", p.display(), e))?;
+        return Ok(token.trim().to_owned());
+    }
+    if let Some(p) = encrypted_token_path(profile).filter(|p| p.exists()) {
+        check_permissions(&p)?;
+        let content =
+            fs::read_to_string(&p).map_err(|e| format!("Could not read {}: {}", p.display(), e))?;
+        let passphrase = rpassword::prompt_password("Passphrase for the api token: ")
+            .map_err(|e| e.to_string())?;
+        return decrypt(&content, &passphrase);
+    }
+    if profile == profile::DEFAULT {
+        Err(format!(
+            "No api token found, run `toggl login` or set {}",
+            ENV_VAR
+        ))
+    } else {
+        Err(format!(
+            "No api token found for profile {}, run `toggl profile add {}` or set {}",
+            profile,
+            profile,
+            env_var(profile)
+        ))
+    }
+}
+
+/// Stores the token, encrypted with `passphrase` if one is given.
+/// Removes the other token file so only one of them is used.
+pub fn store(profile: &str, token: &str, passphrase: Option<&str>) -> Result<PathBuf, String> {
+    let (path, other, content) = match passphrase {
+        Some(pass) => (
+            encrypted_token_path(profile),
+            token_path(profile),
+            encrypt(token, pass)?,
+        ),
+        None => (
+            token_path(profile),
+            encrypted_token_path(profile),
+            token.to_owned(),
+        ),
+    };
+    let path = path.ok_or("Could not find the config directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    write_private(&path, &content)
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    if let Some(other) = other.filter(|p| p.exists()) {
+        fs::remove_file(&other)
+            .map_err(|e| format!("Could not remove {}: {}", other.display(), e))?;
+    }
+    Ok(path)
+}
+
+/// Removes the stored tokens of the profile.
+pub fn remove(profile: &str) -> Result<(), String> {
+    for p in token_path(profile)
+        .into_iter()
+        .chain(encrypted_token_path(profile))
+    {
+        if p.exists() {
+            fs::remove_file(&p).map_err(|e| format!("Could not remove {}: {}", p.display(), e))?;
+        }
+    }
+    if profile != profile::DEFAULT {
+        // only succeeds if nothing else is in there
+        if let Some(d) = profile_dir(profile) {
+            let _ = fs::remove_dir(d);
+        }
+    }
+    Ok(())
+}
+
+/// Names of the profiles with a stored token, besides the default profile.
+pub fn profiles_with_token() -> Vec<String> {
+    let dir = match dirs::config_dir() {
+        Some(d) => d.join("toggl").join("profiles"),
+        None => return Vec::new(),
+    };
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| !matches!(stored(name), Stored::Missing))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the file so that only the user can read it.
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut f = options.open(path)?;
+    #[cfg(unix)]
+    {
+        // an existing file keeps its mode when opened
+        use std::os::unix::fs::PermissionsExt;
+        f.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    f.write_all(content.as_bytes())
+}
+
+/// Refuses token files that other users can read or write.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)
+        .map_err(|e| e.to_string())?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is accessible by other users (mode {:o}), run `chmod 600 {}`",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Encrypts with AES-256-GCM and a key derived from the passphrase, the result is `v1:salt:nonce:ciphertext` in hex.
+fn encrypt(token: &str, passphrase: &str) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, token.as_bytes())
+        .map_err(|_| "Could not encrypt the api token")?;
+    Ok(format!(
+        "{}:{}:{}:{}",
+        ENCRYPTED_VERSION,
+        hex::encode(salt),
+        hex::encode(nonce),
+        hex::encode(ciphertext)
+    ))
+}
+
+fn decrypt(content: &str, passphrase: &str) -> Result<String, String> {
+    let parts = content.trim().split(':').collect::<Vec<_>>();
+    let invalid = || "The encrypted token file is invalid".to_owned();
+    if parts.len() != 4 || parts[0] != ENCRYPTED_VERSION {
+        return Err(invalid());
+    }
+    let salt = hex::decode(parts[1]).map_err(|_| invalid())?;
+    let nonce = hex::decode(parts[2]).map_err(|_| invalid())?;
+    let ciphertext = hex::decode(parts[3]).map_err(|_| invalid())?;
+    if nonce.len() != 12 {
+        return Err(invalid());
+    }
+    let key = derive_key(passphrase, &salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let plain = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| "Wrong passphrase")?;
+    String::from_utf8(plain).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "1971800d4d82861d8f2c1651fea4d212";
+
+    #[test]
+    fn encrypt_round_trip() {
+        let encrypted = encrypt(TOKEN, "secret").unwrap();
+        assert!(encrypted.starts_with("v1:"));
+        assert!(!encrypted.contains(TOKEN));
+        assert_eq!(decrypt(&encrypted, "secret"), Ok(TOKEN.to_owned()));
+        assert_eq!(
+            decrypt(&format!("{}\n", encrypted), "secret"),
+            Ok(TOKEN.to_owned())
+        );
+    }
+
+    #[test]
+    fn salt_and_nonce_are_random() {
+        assert_ne!(
+            encrypt(TOKEN, "secret").unwrap(),
+            encrypt(TOKEN, "secret").unwrap()
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_is_an_error() {
+        let encrypted = encrypt(TOKEN, "secret").unwrap();
+        assert_eq!(
+            decrypt(&encrypted, "Secret"),
+            Err("Wrong passphrase".to_owned())
+        );
+        assert!(decrypt(&encrypted, "").is_err());
+    }
+
+    #[test]
+    fn truncated_or_corrupted_input_is_an_error() {
+        let encrypted = encrypt(TOKEN, "secret").unwrap();
+        let parts = encrypted.split(':').collect::<Vec<_>>();
+        let corrupted = [
+            String::new(),
+            encrypted[..encrypted.len() - 2].to_owned(),
+            encrypted[..encrypted.len() - 1].to_owned(),
+            parts[..3].join(":"),
+            format!("v2:{}", parts[1..].join(":")),
+            format!("{}:{}:{}:", parts[0], parts[1], parts[2]),
+            // nonce of the wrong length
+            format!("{}:{}:{}:{}", parts[0], parts[1], &parts[2][2..], parts[3]),
+            // a flipped bit in the ciphertext
+            format!(
+                "{}:{}:{}:{}{}",
+                parts[0],
+                parts[1],
+                parts[2],
+                &parts[3][..parts[3].len() - 1],
+                if parts[3].ends_with('0') { '1' } else { '0' }
+            ),
+            format!("{}:zz:{}:{}", parts[0], parts[2], parts[3]),
+        ];
+        for c in &corrupted {
+            assert!(decrypt(c, "secret").is_err(), "{:?} should be rejected", c);
+        }
+    }
+}
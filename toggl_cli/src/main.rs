@@ -1,17 +1,26 @@
+mod cache;
+mod config;
+mod credentials;
+mod profile;
+mod select;
+mod timespec;
+
 use ansi_term::Color::{Green, Red};
-use chrono;
 use clap::{App, Arg, ArgMatches, SubCommand};
-use std::fs;
+use config::{Column, ConfigFile, Settings};
+use std::path::PathBuf;
 use std::rc::Rc;
-use toggl_rs::{TimeEntry, Toggl, TogglExt};
+use toggl_rs::workspace::Workspace;
+use toggl_rs::{TimeEntry, Toggl, TogglError, TogglExt};
+use toggl_rs::filter::EntryFilter;
+use toggl_rs::journal::{EntryRef, Journal, Operation};
 use toggl_rs::project::Project;
+use toggl_rs::tag::TagTrait;
+use toggl_rs::time_entry::{history_start, EntryDetails};
+use toggl_rs::summary::{self, Grouping};
 
-fn print_projects(ids: &[String]) {
-    print!("Projects: ");
-    for (i, name) in ids.iter().enumerate() {
-        print!("{}: {}, ", i, name);
-    }
-    println!();
+fn print_projects(names: &[String]) {
+    println!("Projects: {}", names.join(", "));
 }
 
 fn format_duration(c: &chrono::Duration) -> String {
@@ -30,127 +39,328 @@ fn format_duration(c: &chrono::Duration) -> String {
     st
 }
 
-fn print_current(t: &Toggl) {
+/// Operations that could not be sent because we were offline are stored here
+/// Every profile has its own journal so operations are not replayed with another account
+fn journal_path(profile: &str) -> PathBuf {
+    let name = if profile == profile::DEFAULT {
+        "journal.jsonl".to_owned()
+    } else {
+        format!("journal-{}.jsonl", profile)
+    };
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("toggl")
+        .join(name)
+}
+
+fn replay_journal(journal: &mut Journal, t: &Toggl, settings: &Settings) {
+    if journal.is_empty() {
+        return;
+    }
+    match journal.replay(t) {
+        Ok(report) => {
+            println!("Sent {} operations recorded while offline", report.applied);
+            for c in report.conflicts {
+                println!(
+                    "{}: {:?}",
+                    settings.paint(Red, "Dropped because the entry changed on the server"),
+                    c.operation
+                );
+            }
+            for (f, e) in report.failed {
+                println!(
+                    "{}: {:?} ({})",
+                    settings.paint(Red, "Dropped because the api rejected it"),
+                    f.operation,
+                    e
+                );
+            }
+        }
+        Err(ref e) if e.is_offline() => {
+            println!("Offline, {} operations pending", journal.pending().len())
+        }
+        Err(e) => println!("Could not send operations recorded while offline: {}", e),
+    }
+}
+
+fn print_current(t: &Toggl, settings: &Settings) {
     print!("Current: ");
-    let res = t.get_running_entry().expect("API Problem");
+    let res = match t.get_running_entry() {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", settings.paint(Red, &format!("Unknown ({})", e)));
+            return;
+        }
+    };
     if let Some(current) = res {
-        let running_for = chrono::Utc::now() - current.start;
+        let running_for = settings.round(current.elapsed(chrono::Utc::now()));
         println!(
             "{}: {}@{}, {} Running for: {}",
-            Green.paint("Running"),
+            settings.paint(Green, "Running"),
             current.description.unwrap_or_else(|| "".to_string()),
             current
                 .project
                 .as_ref()
                 .map(|v| v.name.as_ref())
                 .unwrap_or(""),
-            current.start.with_timezone(&chrono::Local).format("%H:%M"),
+            settings.format_time(current.start),
             format_duration(&running_for)
         );
     } else {
-        println!("{}", Red.paint("Not Running"));
+        println!("{}", settings.paint(Red, "Not Running"));
     }
 }
 
-fn get_todays_stored_entries(t: &Toggl) -> Vec<TimeEntry> {
-    let start_date = chrono::Utc::today().and_hms(0, 0, 0);
-    let mut entries = t
-        .get_time_entries_range(Some(start_date), None)
-        .expect("API Error");
-    if t.get_running_entry().unwrap_or(None).is_some() {
-        entries.truncate(entries.len() - 1); //the last one is the currently running one which we handle separately
-    }
-    entries
+fn get_stored_entries(
+    t: &Toggl,
+    start: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<TimeEntry>, TogglError> {
+    //the currently running one is handled separately
+    let filter = EntryFilter::new().start(start).running(false);
+    t.get_time_entries_filtered(&filter)
 }
 
-fn print_todays_timeentries(t: &Toggl) {
-    println!(
-        "+-----------------------------------------------------------------------------------+"
-    );
-    let entries = get_todays_stored_entries(t);
-    for (idx, i) in entries.iter().enumerate() {
-        let start_format = i.start.with_timezone(&chrono::Local).format("%H:%M");
-        let stop_format = i
-            .stop
-            .unwrap()
-            .with_timezone(&chrono::Local)
-            .format("%H:%M");
-        let duration = i.stop.unwrap() - i.start;
-        let dur_format = format_duration(&duration);
-        println!(
-            "|{:2} | {} | {} | {:<30} | {:^15} | {:>10} |",
-            idx + 1,
-            start_format,
-            stop_format,
-            i.description.as_ref().unwrap_or(&"".to_string()),
-            i.project.as_ref().map(|v| v.name.as_ref()).unwrap_or(""),
-            dur_format,
-        );
+fn get_todays_stored_entries(
+    t: &Toggl,
+    settings: &Settings,
+) -> Result<Vec<TimeEntry>, TogglError> {
+    get_stored_entries(t, settings.start_of_day(chrono::Utc::now()))
+}
+
+fn cell(
+    settings: &Settings,
+    c: Column,
+    idx: usize,
+    e: &TimeEntry,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    match c {
+        Column::Index => (idx + 1).to_string(),
+        Column::Date => settings.format_date(e.start),
+        Column::Start => settings.format_time(e.start),
+        Column::Stop => settings.format_time(e.effective_stop(now)),
+        Column::Description => e.description.clone().unwrap_or_default(),
+        Column::Project => e
+            .project
+            .as_ref()
+            .map(|v| v.name.clone())
+            .unwrap_or_default(),
+        Column::Tags => e.tags.join(", "),
+        Column::Billable if e.billable => "$".to_owned(),
+        Column::Billable => String::new(),
+        Column::Duration => format_duration(&settings.round(e.elapsed(now))),
     }
-    println!(
-        "+-----------------------------------------------------------------------------------+"
-    );
+}
 
-    //print stats
-    let sum = chrono::Duration::seconds(
-        entries
-            .iter()
-            .map(|t| t.stop.unwrap() - t.start)
-            .map(|t| t.num_seconds())
-            .sum::<i64>(),
-    );
-    let dummy_project = Rc::new(toggl_rs::project::Project {
-        id: -1,
-        name: "No Project".to_string(),
-        billable: false,
-        active: false,
-    });
-    let project_nums = t
-        .projects
+/// Prints the entries of today, or of the current week, as a table with the configured columns.
+fn print_timeentries(t: &Toggl, settings: &Settings, week: bool) {
+    let now = chrono::Utc::now();
+    let entries = if week {
+        get_stored_entries(t, settings.start_of_week(now))
+    } else {
+        get_todays_stored_entries(t, settings)
+    };
+    let entries = match entries {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Could not load entries: {}", e);
+            return;
+        }
+    };
+    let mut default_columns = vec![
+        Column::Index,
+        Column::Start,
+        Column::Stop,
+        Column::Description,
+        Column::Project,
+        Column::Duration,
+    ];
+    if week {
+        default_columns.insert(1, Column::Date);
+    }
+    let columns = settings.columns.as_ref().unwrap_or(&default_columns);
+    let rows = entries
         .iter()
-        .map(|project| {
-            (
-                project.name.clone(),
-                entries
-                    .iter()
-                    .filter(|time_entry| {
-                        time_entry.project.as_ref().unwrap_or(&dummy_project) == project
-                    })
-                    .map(|time_entry| time_entry.stop.unwrap() - time_entry.start)
-                    .map(|time_entry| time_entry.num_seconds())
-                    .sum::<i64>(),
-            )
+        .enumerate()
+        .map(|(idx, e)| {
+            columns
+                .iter()
+                .map(|c| cell(settings, *c, idx, e, now))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            rows.iter()
+                .map(|r| r[i].chars().count())
+                .chain(std::iter::once(c.title().len()))
+                .max()
+                .unwrap_or(0)
         })
-        .collect::<Vec<(String, i64)>>();
+        .collect::<Vec<_>>();
+    let border = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let print_row = |cells: Vec<String>| {
+        let cells = cells
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| format!(" {:<width$} ", v, width = w))
+            .collect::<Vec<_>>();
+        println!("|{}|", cells.join("|"));
+    };
+    println!("+{}+", border);
+    print_row(columns.iter().map(|c| c.title().to_owned()).collect());
+    println!("+{}+", border);
+    for r in rows {
+        print_row(r);
+    }
+    println!("+{}+", border);
 
-    for (name, seconds) in project_nums {
+    //print stats
+    let sum = summary::totals(&entries, now);
+    let groups = if week {
+        group_by_day(&entries, settings, now)
+    } else {
+        summary::group_by(&entries, Grouping::Project, &chrono::Local, now)
+    };
+    for group in groups {
         print!(
             "| {}: {} ({:.2}%) ",
-            name,
-            format_duration(&chrono::Duration::seconds(seconds)),
-            seconds as f64 / sum.num_seconds() as f64
+            group.key,
+            format_duration(&settings.round(group.totals.total)),
+            group.totals.share(&sum) * 100.0
         );
     }
 
     println!(
         "| Total: {} | Ctx: {}",
-        format_duration(&sum),
+        format_duration(&settings.round(sum.total)),
         std::cmp::max(entries.len() as i64 - 1, 0)
     );
 }
 
+/// Groups by the day in the configured timezone.
+fn group_by_day(
+    entries: &[TimeEntry],
+    settings: &Settings,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<summary::Group> {
+    match settings.zone {
+        config::Zone::Local => summary::group_by(entries, Grouping::Day, &chrono::Local, now),
+        config::Zone::Named(tz) => summary::group_by(entries, Grouping::Day, &tz, now),
+        config::Zone::Fixed(o) => summary::group_by(entries, Grouping::Day, &o, now),
+    }
+}
+
+/// The tags given with `--tag`, None if there are none.
+fn tags_arg(matches: &ArgMatches) -> Option<Vec<String>> {
+    matches
+        .values_of("tag")
+        .map(|v| v.map(|s| s.to_owned()).collect())
+}
+
+/// Some(true) for `--billable`, Some(false) for `--no-billable`.
+fn billable_arg(matches: &ArgMatches) -> Option<bool> {
+    if matches.is_present("billable") {
+        Some(true)
+    } else if matches.is_present("no_billable") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The id of the task given with `--task`.
+fn task_arg(matches: &ArgMatches, t: &Toggl, project: Option<&Project>) -> Result<Option<i64>, String> {
+    matches
+        .value_of("task")
+        .map(|q| select::task(t, project, q))
+        .transpose()
+}
+
+/// Finds a workspace of the user by id or name.
+fn find_workspace(t: &Toggl, s: &str) -> Result<Rc<Workspace>, String> {
+    t.user()
+        .workspaces
+        .iter()
+        .find(|w| w.id.to_string() == s || w.name.eq_ignore_ascii_case(s))
+        .cloned()
+        .ok_or_else(|| format!("No workspace named {}", s))
+}
+
+/// The project of a new entry, the configured project if no name is given.
+fn project_arg(
+    t: &Toggl,
+    projects: &[Rc<Project>],
+    settings: &Settings,
+    name: Option<&str>,
+) -> Result<Option<Rc<Project>>, String> {
+    match name.or(settings.project.as_deref()) {
+        Some(name) => select::project(t, projects, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The tags and details of a new entry from the flags and the configuration.
+fn entry_details(
+    matches: &ArgMatches,
+    t: &Toggl,
+    settings: &Settings,
+    project: Option<&Rc<Project>>,
+) -> Result<(Vec<String>, EntryDetails), String> {
+    let workspace = match settings.workspace {
+        Some(ref w) => Some(find_workspace(t, w)?),
+        None => None,
+    };
+    let tags = tags_arg(matches).unwrap_or_else(|| settings.tags.clone());
+    let details = EntryDetails {
+        // the configured workspace is only for entries without a project
+        workspace: workspace.filter(|_| project.is_none()).map(|w| w.id),
+        task: task_arg(matches, t, project.map(|p| &**p))?,
+        billable: billable_arg(matches),
+    };
+    Ok((tags, details))
+}
+
 fn run_matches_time_entry(
     matches: &ArgMatches,
     t: &Toggl,
     projects: &[Rc<Project>],
+    journal: &mut Journal,
+    settings: &Settings,
 ) -> Result<(), String> {
-    if let Some(mut v) = matches.values_of("start") {
-        let title = v.next().map(|v| v.to_owned());
-        let project_idx = v.next().and_then(|s| s.parse::<usize>().ok());
-        let project = project_idx.and_then(|v| projects.get(v));
+    if matches.is_present("start") {
+        let args = matches
+            .values_of("start")
+            .map(|v| v.collect::<Vec<_>>())
+            .unwrap_or_default();
+        let (title, project) = if args.is_empty() && select::interactive() {
+            match select::pick(t, projects, settings.color)? {
+                Some(p) => (p.description, p.project),
+                None => return Err("Nothing selected".into()),
+            }
+        } else {
+            let title = args.first().map(|v| (*v).to_owned());
+            (title, project_arg(t, projects, settings, args.get(1).copied())?)
+        };
+        let (tags, details) = entry_details(matches, t, settings, project.as_ref())?;
 
-        t.start_entry(title.to_owned(), &[], project)
-            .expect("Error");
+        let res = t.start_entry_with(title.to_owned(), &tags, project.as_ref(), &details);
+        match res {
+            Err(ref e) if e.is_offline() => {
+                let wid = t.user().default_wid;
+                journal
+                    .record(Operation::start_with(title.to_owned(), &tags, project.as_ref(), &details, wid))
+                    .map_err(|e| e.to_string())?;
+                println!("Offline, the entry will be sent on the next connection");
+            }
+            r => r.map_err(|e| e.to_string())?,
+        }
         println!(
             "Started Time Entry: {} for Project {}",
             title.unwrap_or_else(|| "".to_string()),
@@ -158,35 +368,39 @@ fn run_matches_time_entry(
         );
         Ok(())
     } else if matches.is_present("stop") {
-        let res = t.get_running_entry().expect("API Error");
-        if let Some(current_entry) = res {
-            t.stop_entry(&current_entry).expect("Error");
+        let offline_stop = |journal: &mut Journal, entry: EntryRef| {
+            journal
+                .record(Operation::Stop {
+                    entry,
+                    stop: chrono::Utc::now(),
+                })
+                .map_err(|e| e.to_string())?;
+            println!("Offline, the entry will be stopped on the next connection");
             Ok(())
-        } else {
-            Err("No time entry currently running".into())
+        };
+        if let Some(guid) = journal.running_offline_entry() {
+            return offline_stop(journal, EntryRef::Guid(guid));
+        }
+        match t.get_running_entry() {
+            Ok(Some(current_entry)) => t.stop_entry(&current_entry).map_err(|e| e.to_string()),
+            Ok(None) => Err("No time entry currently running".into()),
+            Err(ref e) if e.is_offline() => offline_stop(journal, EntryRef::Running),
+            Err(e) => Err(e.to_string()),
         }
     } else if matches.is_present("swap") {
-        let mut entries = get_todays_stored_entries(t);
+        let mut entries = get_todays_stored_entries(t, settings).map_err(|e| e.to_string())?;
         if entries.is_empty() {
             return Err("Not enough entries stored to swap".into());
         }
 
         entries.sort_by(|a, b| b.cmp(a)); //reverse it
-        t.start_entry(
-            entries[0].description.to_owned(),
-            &[],
-            entries[0].project.clone(),
-        )
-        .expect("API Error");
-        Ok(())
+        t.continue_entry(&entries[0]).map_err(|e| e.to_string())
     } else if let Some(id_string) = matches.value_of("delete") {
-        let entries = get_todays_stored_entries(t);
+        let entries = get_todays_stored_entries(t, settings).map_err(|e| e.to_string())?;
         let id = id_string.parse::<usize>();
         if let Ok(id) = id {
-            println!("len, id {} {}", entries.len(), id);
-            if id - 1 < entries.len() {
-                t.delete_entry(&entries[id - 1]).expect("API Error");
-                Ok(())
+            if let Some(entry) = id.checked_sub(1).and_then(|i| entries.get(i)) {
+                t.delete_entry(entry).map_err(|e| e.to_string())
             } else {
                 Err("You tried to delete and entry that does not exist".into())
             }
@@ -194,43 +408,347 @@ fn run_matches_time_entry(
             Err("Could not parse id".into())
         }
     } else if let Some(mut new) = matches.values_of("edit") {
-        let id = new.next().and_then(|s| s.parse::<usize>().ok()).expect("Argument requirement not fulfilled");
-        let new_description = new.next();
-        let new_project_id = new.next().and_then(|s| s.parse::<usize>().ok()).expect("Project is required");
+        let which = new.next().expect("Argument requirement not fulfilled");
+        let entry = find_entry(t, settings, which)?;
+        edit_entry(matches, t, projects, settings, entry, new.next(), new.next())
+    } else {
+        // nothing was parsed which is fine
+        Ok(())
+    }
+}
+
+/// Asks for the api token, checks it with the api and stores it for the profile.
+fn login(matches: &ArgMatches, profile: &str) -> Result<(), String> {
+    let token = rpassword::prompt_password("Api token: ").map_err(|e| e.to_string())?;
+    let t = Toggl::authenticate_api_token(token.trim())
+        .map_err(|e| format!("The token was not accepted: {}", e))?;
+    let passphrase = if matches.is_present("encrypt") {
+        let first = rpassword::prompt_password("Passphrase: ").map_err(|e| e.to_string())?;
+        let second = rpassword::prompt_password("Repeat passphrase: ").map_err(|e| e.to_string())?;
+        if first != second {
+            return Err("The passphrases do not match".into());
+        }
+        Some(first)
+    } else {
+        None
+    };
+    let path = credentials::store(profile, token.trim(), passphrase.as_deref())?;
+    println!(
+        "Logged in as {} in profile {}, the token is stored in {}",
+        t.user().fullname,
+        profile,
+        path.display()
+    );
+    Ok(())
+}
 
-        let entries = get_todays_stored_entries(t);
+fn run_profile(matches: &ArgMatches, config: &ConfigFile, active: &str) -> Result<(), String> {
+    match matches.subcommand() {
+        ("list", _) => {
+            profile::list(config, active);
+            Ok(())
+        }
+        ("add", Some(m)) => {
+            let name = m.value_of("name").expect("name is required");
+            profile::check_name(name)?;
+            login(m, name)
+        }
+        ("remove", Some(m)) => {
+            profile::remove(config, m.value_of("name").expect("name is required"))
+        }
+        ("use", Some(m)) => {
+            profile::use_profile(config, m.value_of("name").expect("name is required"))
+        }
+        _ => Err("Use one of list, add, remove or use".into()),
+    }
+}
 
-        assert!(!entries.is_empty(), "Not enough entries stored to edit");
-        assert!(id - 1 < entries.len(), "No valid entries to swap");
+/// The entry with the number shown in today's table, or the running entry for `running`.
+fn find_entry(t: &Toggl, settings: &Settings, which: &str) -> Result<TimeEntry, String> {
+    if which.eq_ignore_ascii_case("running") {
+        return t
+            .get_running_entry()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "No time entry currently running".to_owned());
+    }
+    let id = which
+        .parse::<usize>()
+        .map_err(|_| format!("{} is neither an entry number nor running", which))?;
+    let entries = get_todays_stored_entries(t, settings).map_err(|e| e.to_string())?;
+    id.checked_sub(1)
+        .and_then(|i| entries.get(i))
+        .cloned()
+        .ok_or_else(|| format!("There is no entry {} today", id))
+}
 
-        let project = projects[new_project_id].clone();
+/// Changes the fields given by flags, `description` and `project` can also be given as values of `te --edit`.
+/// Times are taken on the day the entry starts.
+fn edit_entry(
+    matches: &ArgMatches,
+    t: &Toggl,
+    projects: &[Rc<Project>],
+    settings: &Settings,
+    original: TimeEntry,
+    description: Option<&str>,
+    project: Option<&str>,
+) -> Result<(), String> {
+    let now = chrono::Utc::now();
+    let mut entry = original.clone();
+    if let Some(d) = matches.value_of("description").or(description) {
+        entry.description = Some(d.to_owned()).filter(|v| !v.is_empty());
+    }
+    if let Some(name) = matches.value_of("project").or(project) {
+        let project = select::project(t, projects, name)?;
+        if entry.pid != Some(project.id) {
+            // a task belongs to one project
+            entry.tid = None;
+        }
+        entry.set_project(Some(project));
+    }
+    if let Some(task) = task_arg(matches, t, entry.project.as_deref())? {
+        entry.tid = Some(task);
+    }
+    if let Some(tags) = tags_arg(matches) {
+        entry.tags = tags;
+    }
+    if let Some(billable) = billable_arg(matches) {
+        entry.billable = billable;
+    }
 
-        let mut entry = entries[id - 1].clone();
-        entry.description = new_description.map(|v| v.to_string());
-        entry.project = Some(project);
-        t.update_entry(entry).expect("API Error");
-        Ok(())
+    let date = settings.zone.convert(entry.start).date_naive();
+    let at = |v: &str| timespec::time(v).and_then(|time| settings.zone.at(date, time));
+    let mut stop = if entry.is_running() {
+        None
     } else {
-        // nothing was parsed which is fine
-        Ok(())
+        Some(entry.effective_stop(now))
+    };
+    if let Some(shift) = matches.value_of("shift") {
+        let shift = timespec::offset(shift)?;
+        stop = stop.map(|v| timespec::shift(v, shift)).transpose()?;
+        entry.set_start(timespec::shift(entry.start, shift)?);
+    }
+    if let Some(v) = matches.value_of("start") {
+        entry.set_start(at(v)?);
+    }
+    if let Some(v) = matches.value_of("stop") {
+        stop = Some(at(v)?);
+    }
+    if let Some(v) = matches.value_of("duration") {
+        stop = Some(timespec::shift(entry.start, timespec::duration(v)?)?);
+    }
+    if entry.start > now {
+        return Err("The entry can not start in the future".into());
+    }
+    let timed = ["shift", "start", "stop", "duration"]
+        .iter()
+        .any(|v| matches.is_present(v));
+    if let Some(stop) = stop.filter(|_| timed) {
+        if stop <= entry.start {
+            return Err("The entry has to end after it starts".into());
+        }
+        if stop > now {
+            return Err("The entry can not end in the future".into());
+        }
+        entry.set_stop(stop);
+    }
+    t.update_entry_fields(&original, entry)
+        .map_err(|e| e.to_string())
+}
+
+/// Start and stop of an added entry from `--date`, `--from`, `--to` and `--duration`.
+fn added_range(
+    matches: &ArgMatches,
+    settings: &Settings,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>), String> {
+    let today = settings.zone.convert(now).date_naive();
+    let date = match matches.value_of("date") {
+        Some(v) => timespec::date(v, today)?,
+        None => today,
+    };
+    let at = |v: &str| timespec::time(v).and_then(|time| settings.zone.at(date, time));
+    let from = matches.value_of("from").map(at).transpose()?;
+    let to = matches.value_of("to").map(at).transpose()?;
+    let duration = matches.value_of("duration").map(timespec::duration).transpose()?;
+    let (start, stop) = match (from, to, duration) {
+        (Some(_), Some(_), Some(_)) => {
+            return Err("Give only two of --from, --to and --duration".into())
+        }
+        (Some(from), Some(to), None) => (from, to),
+        (Some(from), None, Some(d)) => (from, timespec::shift(from, d)?),
+        (None, Some(to), Some(d)) => (timespec::shift(to, -d)?, to),
+        (None, None, Some(d)) if date == today => (timespec::shift(now, -d)?, now),
+        (None, None, Some(_)) => return Err("Give --from or --to for entries of other days".into()),
+        _ => return Err("Give --from and --to, or a --duration".into()),
+    };
+    if stop <= start {
+        return Err("The entry has to end after it starts".into());
+    }
+    if stop > now {
+        return Err("The entry can not end in the future".into());
+    }
+    Ok((start, stop))
+}
+
+/// Adds a finished entry after checking that it does not overlap with other entries.
+fn run_add(
+    matches: &ArgMatches,
+    t: &Toggl,
+    projects: &[Rc<Project>],
+    journal: &mut Journal,
+    settings: &Settings,
+) -> Result<(), String> {
+    let now = chrono::Utc::now();
+    let (start, stop) = added_range(matches, settings, now)?;
+    let title = matches.value_of("description").map(|v| v.to_owned());
+    let project = project_arg(t, projects, settings, matches.value_of("project"))?;
+    let (tags, details) = entry_details(matches, t, settings, project.as_ref())?;
+
+    if start < history_start() {
+        println!("Entries of more than three months ago are not returned by the api, overlaps are not checked");
+    }
+    // entries of the day before can reach into the range, the api only returns entries of the last months
+    let existing = t.get_time_entries_range(
+        Some(std::cmp::max(
+            settings.start_of_day(start) - chrono::Duration::days(1),
+            history_start(),
+        )),
+        Some(stop),
+    );
+    match existing {
+        Err(ref e) if e.is_offline() => {
+            journal
+                .record(Operation::create_with(
+                    title.clone(),
+                    &tags,
+                    project.as_ref(),
+                    &details,
+                    t.user().default_wid,
+                    start,
+                    stop - start,
+                ))
+                .map_err(|e| e.to_string())?;
+            println!("Offline, the entry will be sent on the next connection without checking for overlaps");
+        }
+        existing => {
+            let overlapping = existing
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .filter(|e| e.start < stop && e.stop.unwrap_or(now) > start)
+                .map(|e| {
+                    format!(
+                        "{}-{} {}",
+                        settings.format_time(e.start),
+                        e.stop.map(|v| settings.format_time(v)).unwrap_or_default(),
+                        e.description.unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !overlapping.is_empty() {
+                return Err(format!("The entry overlaps with: {}", overlapping.join(", ")));
+            }
+            t.create_entry_with(
+                title.clone(),
+                &tags,
+                project.as_ref(),
+                &details,
+                start,
+                stop - start,
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    println!(
+        "Added Time Entry: {} for Project {} on {} from {} to {}",
+        title.unwrap_or_default(),
+        project.as_ref().map(|v| v.name.as_ref()).unwrap_or(""),
+        settings.format_date(start),
+        settings.format_time(start),
+        settings.format_time(stop)
+    );
+    Ok(())
+}
+
+fn run_tags(matches: &ArgMatches, t: &Toggl, settings: &Settings) -> Result<(), String> {
+    let workspace = match settings.workspace {
+        Some(ref w) => find_workspace(t, w)?,
+        None => t
+            .user()
+            .default_workspace()
+            .ok_or("The default workspace is unknown")?,
+    };
+    let tags = || t.get_tags(&workspace).map_err(|e| e.to_string());
+    match matches.subcommand() {
+        ("create", Some(m)) => {
+            let name = m.value_of("name").expect("name is required");
+            let tag = t.create_tag(name, &workspace).map_err(|e| e.to_string())?;
+            println!("Created tag {} in {}", tag.name, workspace.name);
+            Ok(())
+        }
+        ("rename", Some(m)) => {
+            let old = m.value_of("old").expect("old is required");
+            let new = m.value_of("new").expect("new is required");
+            let tag = tags()?
+                .into_iter()
+                .find(|v| v.name == old)
+                .ok_or_else(|| format!("No tag named {} in {}", old, workspace.name))?;
+            t.rename_tag(&tag, new).map_err(|e| e.to_string())?;
+            println!("Renamed tag {} to {}", old, new);
+            Ok(())
+        }
+        _ => {
+            let mut tags = tags()?;
+            tags.sort_by_key(|v| v.name.to_lowercase());
+            for tag in tags {
+                println!("{}", tag.name);
+            }
+            Ok(())
+        }
     }
 }
 
 fn run_matches(
-    matches: ArgMatches,
+    matches: &ArgMatches,
     t: &Toggl,
     projects: &[Rc<Project>],
+    journal: &mut Journal,
+    settings: &Settings,
 ) -> Result<(), String> {
     if let Some(matches) = matches.subcommand_matches("time_entry") {
-        run_matches_time_entry(matches, t, projects)
+        run_matches_time_entry(matches, t, projects, journal, settings)
     } else if let Some(matches) = matches.subcommand_matches("te") {
-        run_matches_time_entry(matches, t, projects)
+        run_matches_time_entry(matches, t, projects, journal, settings)
+    } else if let Some(matches) = matches.subcommand_matches("add") {
+        run_add(matches, t, projects, journal, settings)
+    } else if let Some(matches) = matches.subcommand_matches("edit") {
+        let which = matches.value_of("entry").expect("entry is required");
+        let entry = find_entry(t, settings, which)?;
+        edit_entry(matches, t, projects, settings, entry, None, None)
+    } else if let Some(matches) = matches.subcommand_matches("tags") {
+        run_tags(matches, t, settings)
     } else {
         Ok(())
     }
 }
 
 fn main() {
+    let tag_arg = Arg::with_name("tag")
+        .long("tag")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Tag of the entry, can be given several times. Replaces the tags of the config");
+    let billable_arg = Arg::with_name("billable")
+        .long("billable")
+        .help("Marks the entry as billable");
+    let no_billable_arg = Arg::with_name("no_billable")
+        .long("no-billable")
+        .conflicts_with("billable")
+        .help("Marks the entry as not billable");
+    let task_arg = Arg::with_name("task")
+        .long("task")
+        .takes_value(true)
+        .help("Task of the entry, by name or id");
     let te_match = ["time_entry", "te"].iter().map(|v|
         SubCommand::with_name(v)
             .about("Modifies Time Entries")
@@ -238,7 +756,7 @@ fn main() {
                 Arg::with_name("start")
                     .short("s")
                     .long("start")
-                    .help("Starts a time entry with the appropriate id (optional values description, projectid)")
+                    .help("Starts a time entry (optional values description, project). The project is a name, a unique prefix, a fuzzy match or @client/project. Without values a picker over recent entries and projects is shown on a terminal")
                     .min_values(0)
                     .max_values(2)
                     .takes_value(true),
@@ -265,29 +783,282 @@ fn main() {
                 Arg::with_name("edit")
                     .short("e")
                     .long("edit")
-                    .number_of_values(3)
-                    .value_names(&["timeentry_number", "new_description", "new_project_id"])
-                    .help("Edits the entry given by the first "),
-            ));
+                    .min_values(1)
+                    .max_values(3)
+                    .value_name("timeentry_number")
+                    .help("Edits the entry with the number (or running), optional values new description, new project. See `toggl edit` for times"),
+            )
+            .arg(tag_arg.clone())
+            .arg(billable_arg.clone())
+            .arg(no_billable_arg.clone())
+            .arg(task_arg.clone()));
+    let edit_match = SubCommand::with_name("edit")
+        .about("Changes any fields of an entry, e.g., `toggl edit running --start 09:15`")
+        .arg(
+            Arg::with_name("entry")
+                .required(true)
+                .help("Number of the entry in today's table or running"),
+        )
+        .arg(
+            Arg::with_name("description")
+                .long("description")
+                .takes_value(true)
+                .help("New description, an empty one removes it"),
+        )
+        .arg(
+            Arg::with_name("project")
+                .long("project")
+                .takes_value(true)
+                .help("Project by name, unique prefix, fuzzy match or @client/project"),
+        )
+        .arg(
+            Arg::with_name("start")
+                .long("start")
+                .takes_value(true)
+                .help("Start time, e.g., 09:15. The stop time stays the same"),
+        )
+        .arg(
+            Arg::with_name("stop")
+                .long("stop")
+                .takes_value(true)
+                .help("Stop time, e.g., 11:00. A running entry is stopped"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .conflicts_with("stop")
+                .help("Duration like 1h30m, 90m or 1:30, moves the stop time"),
+        )
+        .arg(
+            Arg::with_name("shift")
+                .long("shift")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(&["start", "stop"])
+                .help("Moves the entry by a duration like +10m or -1h"),
+        )
+        .arg(tag_arg.clone())
+        .arg(billable_arg.clone())
+        .arg(no_billable_arg.clone())
+        .arg(task_arg.clone());
+    let add_match = SubCommand::with_name("add")
+        .about("Adds a finished entry, e.g., `toggl add \"Review\" --project X --from 09:30 --to 11:00`")
+        .arg(Arg::with_name("description").help("Description of the entry"))
+        .arg(
+            Arg::with_name("project")
+                .long("project")
+                .takes_value(true)
+                .help("Project by name, unique prefix, fuzzy match or @client/project"),
+        )
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Start time, e.g., 09:30"),
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .help("Stop time, e.g., 11:00"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .help("Duration like 1h30m, 90m or 1:30. Without --from or --to the entry ends now"),
+        )
+        .arg(
+            Arg::with_name("date")
+                .long("date")
+                .takes_value(true)
+                .help("Day of the entry: today (default), yesterday or YYYY-MM-DD"),
+        )
+        .arg(tag_arg.clone())
+        .arg(billable_arg.clone())
+        .arg(no_billable_arg.clone())
+        .arg(task_arg.clone());
+    let encrypt_arg = Arg::with_name("encrypt")
+        .long("encrypt")
+        .help("Encrypts the stored token with a passphrase that is asked for on every run");
+    let login_match = SubCommand::with_name("login")
+        .about("Checks an api token and stores it for the current profile")
+        .arg(encrypt_arg.clone());
+    let name_arg = Arg::with_name("name").required(true).help("Name of the profile");
+    let profile_match = SubCommand::with_name("profile")
+        .about("Manages profiles, each with its own token and settings")
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists the profiles, the current one is marked with *"),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Checks an api token and stores it for a new profile")
+                .arg(name_arg.clone())
+                .arg(encrypt_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Removes the stored token of the profile")
+                .arg(name_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("use")
+                .about("Uses the profile when neither --profile nor TOGGL_PROFILE are given")
+                .arg(name_arg),
+        );
+    let tags_match = SubCommand::with_name("tags")
+        .about("Manages the tags of the workspace given with --workspace or the default workspace")
+        .subcommand(SubCommand::with_name("list").about("Lists the tags"))
+        .subcommand(
+            SubCommand::with_name("create")
+                .about("Creates a tag")
+                .arg(Arg::with_name("name").required(true).help("Name of the tag")),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Renames a tag, entries keep it under the new name")
+                .arg(Arg::with_name("old").required(true).help("Current name of the tag"))
+                .arg(Arg::with_name("new").required(true).help("New name of the tag")),
+        );
+    let value_arg = |name: &'static str, long: &'static str, help: &'static str| {
+        Arg::with_name(name).long(long).takes_value(true).help(help)
+    };
     let matches = App::new("toggl")
         .about("Controls toggl")
+        .after_help(
+            "The api token is taken from TOGGL_API_TOKEN or the file stored by `toggl login`.\n\
+             Defaults for the options are read from config.toml in the config directory.",
+        )
+        .arg(value_arg(
+            "config",
+            "config",
+            "Reads the settings from this file instead of the default config",
+        ))
+        .arg(value_arg(
+            "profile",
+            "profile",
+            "Uses the token and settings of this profile, see `toggl profile`",
+        ))
+        .arg(value_arg(
+            "workspace",
+            "workspace",
+            "Name or id of the workspace entries without a project are started in",
+        ))
+        .arg(value_arg(
+            "timezone",
+            "timezone",
+            "Timezone for showing times: local, a name like Europe/Berlin or an offset like +02:00",
+        ))
+        .arg(value_arg("week_start", "week-start", "First day of the week, e.g., mon or sun"))
+        .arg(value_arg("date_format", "date-format", "strftime format for dates"))
+        .arg(value_arg("time_format", "time-format", "strftime format for times"))
+        .arg(value_arg("rounding", "rounding", "Rounds shown durations to this many minutes"))
+        .arg(value_arg(
+            "columns",
+            "columns",
+            "Comma separated columns of the entry table: index, date, start, stop, description, project, tags, billable, duration",
+        ))
+        .arg(Arg::with_name("color").long("color").help("Colors the output"))
+        .arg(
+            Arg::with_name("no_color")
+                .long("no-color")
+                .conflicts_with("color")
+                .help("Does not color the output"),
+        )
+        .arg(
+            Arg::with_name("week")
+                .long("week")
+                .help("Shows the entries of the current week instead of today"),
+        )
         .subcommands(te_match)
+        .subcommand(login_match)
+        .subcommand(profile_match)
+        .subcommand(tags_match)
+        .subcommand(add_match)
+        .subcommand(edit_match)
         .get_matches();
 
-    let credentials = fs::read_to_string("api_token")
-        .expect("Please supply a file called api_token with your api_token");
-    let toggl = Toggl::init(&credentials).expect("Could not connect to toggl");
-    let projects = &toggl.projects;
-    let project_ids = projects
+    let config_file = match matches.value_of("config") {
+        Some(p) => ConfigFile::load(std::path::Path::new(p)),
+        None => ConfigFile::default_path()
+            .map(|p| ConfigFile::load(&p))
+            .unwrap_or_else(|| Ok(ConfigFile::default())),
+    };
+    let (config_file, profile) = match config_file
+        .and_then(|c| profile::active(matches.value_of("profile")).map(|p| (c, p)))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(m) = matches.subcommand_matches("login") {
+        if let Err(s) = login(m, &profile) {
+            println!("Error in executing: {}", s);
+        }
+        return;
+    }
+    if let Some(m) = matches.subcommand_matches("profile") {
+        if let Err(s) = run_profile(m, &config_file, &profile) {
+            println!("Error in executing: {}", s);
+        }
+        return;
+    }
+
+    let settings = match config_file
+        .for_profile(&profile)
+        .and_then(|f| Settings::new(f, &matches))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let api_token = match credentials::resolve(&profile) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let read_only = matches.subcommand_name().is_none();
+    let toggl = match cache::connect(&api_token, read_only) {
+        Ok(v) => v,
+        Err(ref e) if e.is_offline() => {
+            eprintln!("Could not reach toggl and there is no cached data yet, connect once to use it offline");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not connect to toggl: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut journal = match Journal::open(&journal_path(&profile)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Could not read the offline journal: {}", e);
+            std::process::exit(1);
+        }
+    };
+    replay_journal(&mut journal, &toggl, &settings);
+    let projects = toggl.projects();
+    let project_names = projects
         .iter()
         .map(|p| p.name.clone())
         .collect::<Vec<String>>();
 
-    print_projects(&project_ids);
-    if let Err(s) = run_matches(matches, &toggl, projects) {
+    print_projects(&project_names);
+    if let Err(s) = run_matches(&matches, &toggl, projects, &mut journal, &settings) {
         println!("Error in executing: {}", s);
     } else {
-        print_current(&toggl);
-        print_todays_timeentries(&toggl);
+        print_current(&toggl, &settings);
+        print_timeentries(&toggl, &settings, matches.is_present("week"));
     }
+    cache::store(&api_token, &toggl);
 }
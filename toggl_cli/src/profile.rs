@@ -0,0 +1,114 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::ConfigFile;
+use crate::credentials::{self, Stored};
+
+/// The profile used if none is selected, its token is stored directly in the config directory
+pub const DEFAULT: &str = "default";
+
+/// Selects the profile if `--profile` is not given
+const ENV_VAR: &str = "TOGGL_PROFILE";
+
+/// Contains the name of the profile selected with `toggl profile use`.
+fn active_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("toggl").join("profile"))
+}
+
+/// Profile names are used in paths, so only letters, digits, `-` and `_` are allowed.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name {}, use letters, digits, - and _",
+            name
+        ))
+    }
+}
+
+/// The profile from the flag, the environment or `toggl profile use`, in this order.
+pub fn active(flag: Option<&str>) -> Result<String, String> {
+    let name = flag
+        .map(|v| v.to_owned())
+        .or_else(|| std::env::var(ENV_VAR).ok().filter(|v| !v.is_empty()))
+        .or_else(|| {
+            active_path()
+                .and_then(|p| fs::read_to_string(p).ok())
+                .map(|v| v.trim().to_owned())
+                .filter(|v| !v.is_empty())
+        })
+        .unwrap_or_else(|| DEFAULT.to_owned());
+    check_name(&name)?;
+    Ok(name)
+}
+
+/// All profiles that have a token or settings.
+fn known(config: &ConfigFile) -> BTreeSet<String> {
+    let mut names = credentials::profiles_with_token()
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    names.extend(config.profiles.keys().cloned());
+    names.insert(DEFAULT.to_owned());
+    names
+}
+
+pub fn list(config: &ConfigFile, active: &str) {
+    for name in known(config) {
+        let token = match credentials::stored(&name) {
+            Stored::Plain => "token stored",
+            Stored::Encrypted => "encrypted token stored",
+            Stored::Missing => "no token",
+        };
+        let settings = if config.profiles.contains_key(&name) {
+            ", has settings"
+        } else {
+            ""
+        };
+        let marker = if name == active { "*" } else { " " };
+        println!("{} {} ({}{})", marker, name, token, settings);
+    }
+}
+
+/// Makes the profile the one used when neither `--profile` nor the environment select one.
+pub fn use_profile(config: &ConfigFile, name: &str) -> Result<(), String> {
+    check_name(name)?;
+    if !known(config).contains(name) {
+        return Err(format!(
+            "Unknown profile {}, add it with `toggl profile add {}`",
+            name, name
+        ));
+    }
+    let path = active_path().ok_or("Could not find the config directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, name).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    println!("Using profile {}", name);
+    Ok(())
+}
+
+/// Removes the stored token of the profile, its settings in the config file are left alone.
+pub fn remove(config: &ConfigFile, name: &str) -> Result<(), String> {
+    check_name(name)?;
+    credentials::remove(name)?;
+    if let Some(p) = active_path() {
+        let selected = fs::read_to_string(&p).map(|v| v.trim() == name);
+        if selected.unwrap_or(false) {
+            fs::remove_file(&p).map_err(|e| format!("Could not remove {}: {}", p.display(), e))?;
+        }
+    }
+    println!("Removed the token of profile {}", name);
+    if config.profiles.contains_key(name) {
+        println!(
+            "The settings of the profile are still in the config file under [profiles.{}]",
+            name
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use dialoguer::FuzzySelect;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::collections::HashSet;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::rc::Rc;
+use toggl_rs::client::ClientTrait;
+use toggl_rs::project::Project;
+use toggl_rs::task::TaskTrait;
+use toggl_rs::{Toggl, TogglExt};
+
+/// At most this many candidates are offered when a name is ambiguous
+const MAX_CANDIDATES: usize = 10;
+
+/// Descriptions of entries from this many days are offered by the picker
+const RECENT_DAYS: i64 = 30;
+/// At most this many recent description and project pairs are offered by the picker
+const MAX_RECENT: usize = 50;
+
+/// The items whose name equals the query, or else starts with it, or else fuzzy matches it (best first).
+/// Case is ignored.
+fn matching<'a, T, F: Fn(&T) -> &str>(items: &'a [T], query: &str, name: F) -> Vec<&'a T> {
+    let query = query.trim().to_lowercase();
+    let exact = items
+        .iter()
+        .filter(|v| name(v).to_lowercase() == query)
+        .collect::<Vec<_>>();
+    if !exact.is_empty() {
+        return exact;
+    }
+    let prefix = items
+        .iter()
+        .filter(|v| name(v).to_lowercase().starts_with(&query))
+        .collect::<Vec<_>>();
+    if !prefix.is_empty() {
+        return prefix;
+    }
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut fuzzy = items
+        .iter()
+        .filter_map(|v| matcher.fuzzy_match(name(v), &query).map(|s| (s, v)))
+        .collect::<Vec<_>>();
+    fuzzy.sort_by_key(|v| std::cmp::Reverse(v.0));
+    fuzzy.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Finds a project by its name, a unique prefix, a fuzzy match or `@client/project`.
+/// If several projects match, asks which one is meant when running in a terminal and fails otherwise.
+pub fn project(t: &Toggl, projects: &[Rc<Project>], query: &str) -> Result<Rc<Project>, String> {
+    let (candidates, name) = match query.strip_prefix('@') {
+        Some(path) => {
+            let (client, name) = match path.find('/') {
+                Some(i) => (&path[..i], &path[i + 1..]),
+                None => (path, ""),
+            };
+            let clients = t
+                .get_clients()
+                .map_err(|e| format!("Could not load clients: {}", e))?;
+            let ids = matching(&clients, client, |c| &c.name)
+                .iter()
+                .map(|c| c.id)
+                .collect::<HashSet<_>>();
+            if ids.is_empty() {
+                return Err(format!("No client matching {}", client));
+            }
+            let of_client = projects
+                .iter()
+                .filter(|p| p.cid.is_some_and(|c| ids.contains(&c)))
+                .cloned()
+                .collect::<Vec<_>>();
+            (of_client, name)
+        }
+        None => (projects.to_vec(), query),
+    };
+    let found = matching(&candidates, name, |p| &p.name);
+    match found.len() {
+        0 => Err(format!("No project matching {}", query)),
+        1 => Ok(found[0].clone()),
+        _ => choose(t, query, &found),
+    }
+}
+
+/// Finds a task of the project by its id or, like projects, by its name.
+pub fn task(t: &Toggl, project: Option<&Project>, query: &str) -> Result<i64, String> {
+    if let Ok(id) = query.trim().parse::<i64>() {
+        return Ok(id);
+    }
+    let project = project.ok_or("A task needs a project")?;
+    let tasks = t
+        .get_tasks(project)
+        .map_err(|e| format!("Could not load tasks: {}", e))?;
+    let found = matching(&tasks, query, |v| &v.name);
+    match found.len() {
+        0 => Err(format!("No task of {} matching {}", project.name, query)),
+        1 => Ok(found[0].id),
+        _ => Err(format!(
+            "{} is ambiguous, it matches: {}",
+            query,
+            found
+                .iter()
+                .take(MAX_CANDIDATES)
+                .map(|v| v.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// The project name together with its workspace, as names can repeat across workspaces.
+fn describe(t: &Toggl, p: &Project) -> String {
+    match t.workspace(p.wid) {
+        Some(w) => format!("{} ({})", p.name, w.name),
+        None => p.name.clone(),
+    }
+}
+
+fn choose(t: &Toggl, query: &str, found: &[&Rc<Project>]) -> Result<Rc<Project>, String> {
+    let found = &found[..found.len().min(MAX_CANDIDATES)];
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        let names = found
+            .iter()
+            .map(|p| describe(t, p))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!("{} is ambiguous, it matches: {}", query, names));
+    }
+    eprintln!("{} matches several projects:", query);
+    for (i, p) in found.iter().enumerate() {
+        eprintln!("{:3}: {}", i + 1, describe(t, p));
+    }
+    eprint!("Project number: ");
+    io::stderr().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    line.trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| i.checked_sub(1))
+        .and_then(|i| found.get(i))
+        .map(|p| Rc::clone(p))
+        .ok_or_else(|| "No project chosen".to_owned())
+}
+
+/// A choice of the picker.
+pub struct Pick {
+    pub description: Option<String>,
+    pub project: Option<Rc<Project>>,
+}
+
+/// True if the user can answer questions.
+pub fn interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Lets the user choose between recently used description and project pairs (newest first) and all active projects.
+/// Typing filters the list. Returns None if the user cancelled.
+pub fn pick(t: &Toggl, projects: &[Rc<Project>], color: bool) -> Result<Option<Pick>, String> {
+    let now = chrono::Utc::now();
+    let mut entries = t
+        .get_time_entries_range(Some(now - chrono::Duration::days(RECENT_DAYS)), Some(now))
+        .map_err(|e| format!("Could not load recent entries: {}", e))?;
+    entries.sort_by(|a, b| b.cmp(a));
+
+    let mut seen = HashSet::new();
+    let mut picks = entries
+        .into_iter()
+        .filter(|e| e.description.is_some())
+        .filter(|e| seen.insert((e.description.clone(), e.project_id())))
+        .take(MAX_RECENT)
+        .map(|e| Pick {
+            description: e.description,
+            project: e.project,
+        })
+        .collect::<Vec<_>>();
+    picks.extend(projects.iter().filter(|p| p.active).map(|p| Pick {
+        description: None,
+        project: Some(p.clone()),
+    }));
+    if picks.is_empty() {
+        return Err("Nothing to pick from, give a description or project".into());
+    }
+
+    let labels = picks
+        .iter()
+        .map(|p| {
+            let project = p
+                .project
+                .as_ref()
+                .map(|v| format!("@{}", v.name))
+                .unwrap_or_default();
+            match p.description {
+                Some(ref d) => format!("{} {}", d, project),
+                None => project,
+            }
+        })
+        .collect::<Vec<_>>();
+    let theme: &dyn Theme = if color {
+        &ColorfulTheme::default()
+    } else {
+        &SimpleTheme
+    };
+    let chosen = FuzzySelect::with_theme(theme)
+        .with_prompt("Start")
+        .items(&labels)
+        .default(0)
+        .max_length(15)
+        .interact_opt()
+        .map_err(|e| e.to_string())?;
+    Ok(chosen.map(|i| picks.swap_remove(i)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_order() {
+        let names = ["Development", "dev", "Devops", "Meetings", "Design review"];
+        let cases: &[(&str, &[&str])] = &[
+            // exact matches win over earlier prefix matches, case is ignored
+            ("DEV", &["dev"]),
+            (" dev ", &["dev"]),
+            ("meetings", &["Meetings"]),
+            // all prefix matches in the order of the items
+            ("deve", &["Development"]),
+            ("de", &["Development", "dev", "Devops", "Design review"]),
+            // fuzzy matches only if nothing starts with the query
+            ("mtgs", &["Meetings"]),
+            ("review", &["Design review"]),
+            ("xyz", &[]),
+        ];
+        for (query, expected) in cases {
+            let found = matching(&names, query, |v| v);
+            assert_eq!(
+                &found.into_iter().copied().collect::<Vec<_>>(),
+                expected,
+                "query {:?}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn fuzzy_matches_best_first() {
+        let names = ["Project planning", "Team planning"];
+        assert_eq!(
+            matching(&names, "tplan", |v| v),
+            vec![&"Team planning", &"Project planning"]
+        );
+    }
+}
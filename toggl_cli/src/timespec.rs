@@ -0,0 +1,165 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+
+/// `today`, `yesterday` or a date like `2020-03-01`.
+pub fn date(s: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    match s.trim().to_lowercase().as_str() {
+        "today" => Ok(today),
+        "yesterday" => today
+            .pred_opt()
+            .ok_or_else(|| "There is no yesterday".to_owned()),
+        v => NaiveDate::parse_from_str(v, "%Y-%m-%d")
+            .map_err(|_| format!("Unknown date {}, use today, yesterday or YYYY-MM-DD", s)),
+    }
+}
+
+/// A time of the day like `9:30` or `09:30:15`.
+pub fn time(s: &str) -> Result<NaiveTime, String> {
+    let s = s.trim();
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+        .map_err(|_| format!("Unknown time {}, use HH:MM", s))
+}
+
+/// A number without sign.
+fn number(s: &str) -> Option<i64> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// A duration like `1h30m`, `90m`, `45s` or `1:30`. A plain number is taken as minutes.
+/// Durations too large for chrono are an error.
+pub fn duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("Unknown duration {}, use e.g. 1h30m, 90m or 1:30", s);
+    if let Some((h, m)) = s.split_once(':') {
+        let h = number(h).and_then(Duration::try_hours);
+        let m = number(m)
+            .filter(|m| *m < 60)
+            .and_then(Duration::try_minutes);
+        return match (h, m) {
+            (Some(h), Some(m)) => h.checked_add(&m).ok_or_else(invalid),
+            _ => Err(invalid()),
+        };
+    }
+    if let Some(m) = number(s) {
+        return Duration::try_minutes(m).ok_or_else(invalid);
+    }
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n = number(&digits).ok_or_else(invalid)?;
+        digits.clear();
+        let d = match c {
+            'h' => Duration::try_hours(n),
+            'm' => Duration::try_minutes(n),
+            's' => Duration::try_seconds(n),
+            _ => None,
+        };
+        total = d.and_then(|d| total.checked_add(&d)).ok_or_else(invalid)?;
+    }
+    if !digits.is_empty() || s.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// A duration with an optional sign like `+10m` or `-1h`.
+pub fn offset(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    match s.strip_prefix('-') {
+        Some(v) => duration(v).map(|d| -d),
+        None => duration(s.strip_prefix('+').unwrap_or(s)),
+    }
+}
+
+/// `t` moved by `d`, an error instead of a panic if the result is out of range.
+pub fn shift(t: DateTime<Utc>, d: Duration) -> Result<DateTime<Utc>, String> {
+    t.checked_add_signed(d)
+        .ok_or_else(|| "The time is out of range".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn dates() {
+        let today = day(2020, 3, 1);
+        assert_eq!(date("today", today), Ok(today));
+        assert_eq!(date(" Yesterday ", today), Ok(day(2020, 2, 29)));
+        assert_eq!(date("2019-12-31", today), Ok(day(2019, 12, 31)));
+        assert!(date("", today).is_err());
+        assert!(date("2019-02-30", today).is_err());
+        assert!(date("tomorrow", today).is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(time("9:30"), Ok(NaiveTime::from_hms_opt(9, 30, 0).unwrap()));
+        assert_eq!(
+            time("09:30:15"),
+            Ok(NaiveTime::from_hms_opt(9, 30, 15).unwrap())
+        );
+        assert!(time("").is_err());
+        assert!(time("24:00").is_err());
+        assert!(time("9:75").is_err());
+        assert!(time("930").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(duration("2h"), Ok(Duration::hours(2)));
+        assert_eq!(duration("45s"), Ok(Duration::seconds(45)));
+        assert_eq!(duration("90"), Ok(Duration::minutes(90)));
+        assert_eq!(duration("1:30"), Ok(Duration::minutes(90)));
+        assert_eq!(duration("0:05"), Ok(Duration::minutes(5)));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for s in &[
+            "", "h", "1h30", "1:75", "1:", ":30", "-5", "1:-5", "+5", "1d", "1.5h",
+        ] {
+            assert!(duration(s).is_err(), "{} should be invalid", s);
+        }
+    }
+
+    #[test]
+    fn huge_durations_are_errors() {
+        assert!(duration("9999999999999999h").is_err());
+        assert!(duration("9223372036854775807").is_err());
+        assert!(duration("99999999999999999999m").is_err());
+        assert!(duration("9999999999999:00").is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(offset("+10m"), Ok(Duration::minutes(10)));
+        assert_eq!(offset("-1h"), Ok(Duration::hours(-1)));
+        assert_eq!(offset("10m"), Ok(Duration::minutes(10)));
+        assert!(offset("--10m").is_err());
+        assert!(offset("+-10m").is_err());
+    }
+
+    #[test]
+    fn shifts() {
+        let t = Utc::now();
+        assert_eq!(
+            shift(t, Duration::minutes(10)),
+            Ok(t + Duration::minutes(10))
+        );
+        assert!(shift(t, Duration::MAX).is_err());
+        assert!(shift(t, Duration::MIN).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::rc::Rc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use toggl_rs::project::Project;
+use toggl_rs::user::User;
+use toggl_rs::workspace::Workspace;
+use toggl_rs::{TimeEntryInner, Toggl};
+
+const WORKSPACES: i64 = 5;
+const PROJECTS: i64 = 2000;
+/// Roughly a year of entries
+const ENTRIES: i64 = 10_000;
+
+fn toggl() -> Toggl {
+    let user = User {
+        fullname: "Bench".to_string(),
+        workspaces: (0..WORKSPACES)
+            .map(|id| {
+                Rc::new(Workspace {
+                    id,
+                    name: format!("Workspace {}", id),
+                    extra: serde_json::Map::new(),
+                })
+            })
+            .collect(),
+        default_wid: 0,
+    };
+    let projects = (0..PROJECTS)
+        .map(|id| Project {
+            id,
+            wid: id % WORKSPACES,
+            cid: None,
+            name: format!("Project {}", id),
+            billable: false,
+            active: true,
+            extra: serde_json::Map::new(),
+        })
+        .collect();
+    Toggl::from_parts("api_token", user, projects)
+}
+
+fn entries() -> Vec<TimeEntryInner> {
+    let start = chrono::Utc::now() - chrono::Duration::days(365);
+    (0..ENTRIES)
+        .map(|id| {
+            let pid = if id % 10 == 0 { None } else { Some((id * 7) % PROJECTS) };
+            let entry_start = start + chrono::Duration::minutes(id * 50);
+            TimeEntryInner {
+                id,
+                guid: uuid::Uuid::new_v4(),
+                wid: id % WORKSPACES,
+                pid,
+                tid: None,
+                start: entry_start,
+                stop: Some(entry_start + chrono::Duration::minutes(45)),
+                duration: 45 * 60,
+                description: Some(format!("Entry {}", id)),
+                tags: Vec::new(),
+                billable: false,
+                duronly: false,
+                at: entry_start,
+                extra: serde_json::Map::new(),
+            }
+        })
+        .collect()
+}
+
+fn resolve_entries(c: &mut Criterion) {
+    let t = toggl();
+    let raw = entries();
+    c.bench_function("resolve a year of entries with 2000 projects", |b| {
+        b.iter(|| t.resolve_entries(black_box(&raw)))
+    });
+}
+
+criterion_group!(benches, resolve_entries);
+criterion_main!(benches);
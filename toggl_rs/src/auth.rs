@@ -1,45 +1,235 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::TogglError;
+use crate::user::User;
 use crate::workspace::Workspace;
 
+use crate::ApiVersion;
+use crate::Query;
 use crate::Toggl;
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct UserJSON {
     pub fullname: String,
+    /// Id of the workspace entries are created in when no workspace is given
+    pub default_wid: i64,
     pub workspaces: Vec<Workspace>,
+    #[serde(default, skip_serializing)]
+    pub api_token: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct InitResponse {
-    since: i64,
+    pub since: i64,
     pub data: UserJSON,
 }
 
+/// The user as returned by `/me` of api v9, the workspaces have to be queried separately
+#[derive(Deserialize, Debug)]
+struct UserV9 {
+    fullname: String,
+    default_workspace_id: i64,
+    #[serde(default)]
+    api_token: Option<String>,
+}
+
+/// How requests to the api are authenticated.
+#[derive(Clone)]
+pub enum Credentials {
+    /// The api token from the profile page of the user
+    ApiToken(String),
+    /// Email and password of the user
+    Password { email: String, password: String },
+    /// The cookies of a session, see `Toggl::start_session`
+    Session(String),
+}
+
+impl Credentials {
+    pub(crate) fn apply(&self, r: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            Credentials::ApiToken(t) => r.basic_auth(t, Some("api_token")),
+            Credentials::Password { email, password } => r.basic_auth(email, Some(password)),
+            Credentials::Session(c) => r.header(reqwest::header::COOKIE, c.as_str()),
+        }
+    }
+}
+
+/// Does not print the secrets
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Credentials::ApiToken(_) => write!(f, "ApiToken"),
+            Credentials::Password { email, .. } => write!(f, "Password {{ email: {:?} }}", email),
+            Credentials::Session(_) => write!(f, "Session"),
+        }
+    }
+}
+
 impl Toggl {
-    pub fn authenticate_api_token(api_token: &str) -> Result<Toggl, crate::error::TogglError> {
+    /// Reloads the information of the user and the workspaces from the api.
+    pub fn refresh_user(&mut self) -> Result<(), crate::error::TogglError> {
+        match self.api {
+            ApiVersion::V8 => {
+                let init_response: InitResponse = self.get(&self.url("/me"))?;
+                self.set_user_v8(init_response);
+            }
+            ApiVersion::V9 => {
+                let user: UserV9 = self.get(&self.url("/me"))?;
+                self.set_user_v9(user)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_user_v8(&mut self, mut init_response: InitResponse) {
+        if let Some(token) = init_response.data.api_token.take() {
+            self.api_token = Some(token);
+        }
+        self.since = init_response.since;
+        self.user = init_response.into();
+        self.reindex();
+    }
+
+    /// Sets the user and queries its workspaces, which are not part of `/me` in api v9.
+    fn set_user_v9(&mut self, user: UserV9) -> Result<(), crate::error::TogglError> {
+        let since = chrono::Utc::now().timestamp();
+        let workspaces: Vec<Workspace> = self.get(&self.url("/me/workspaces"))?;
+        if let Some(token) = user.api_token {
+            self.api_token = Some(token);
+        }
+        self.since = since;
+        self.user = User {
+            fullname: user.fullname,
+            workspaces: workspaces.into_iter().map(Rc::new).collect(),
+            default_wid: user.default_workspace_id,
+        };
+        self.reindex();
+        Ok(())
+    }
+
+    pub fn authenticate_api_token(api_token: &str) -> Result<Toggl, TogglError> {
+        Toggl::authenticate_api_token_with_version(api_token, ApiVersion::V8)
+    }
+
+    /// Checks the token against the given version of the api and loads the user.
+    pub fn authenticate_api_token_with_version(
+        api_token: &str,
+        api: ApiVersion,
+    ) -> Result<Toggl, TogglError> {
+        Toggl::authenticate(Credentials::ApiToken(api_token.trim_end().to_owned()), api)
+    }
+
+    /// Authenticates with email and password, the api token of the user is available afterwards with `api_token`.
+    pub fn authenticate_password(email: &str, password: &str) -> Result<Toggl, TogglError> {
+        Toggl::authenticate(
+            Credentials::Password {
+                email: email.to_owned(),
+                password: password.to_owned(),
+            },
+            ApiVersion::V8,
+        )
+    }
+
+    /// Checks the credentials against the given version of the api and loads the user.
+    pub fn authenticate(credentials: Credentials, api: ApiVersion) -> Result<Toggl, TogglError> {
         let client = reqwest::Client::new();
-        let ap = api_token.trim_end();
-        let mut resp = client
-            .get("https://www.toggl.com/api/v8/me")
-            .basic_auth(ap, Some("api_token"))
+        let mut resp = credentials
+            .apply(client.get(&format!("{}/me", api.base_url())))
             .send()?;
         if resp.status().is_success() {
-            let init_response: InitResponse = resp.json()?;
-
-            Ok(Toggl {
-                api_token: ap.to_owned(),
-                client,
-                user: init_response.into(),
-                projects: Vec::new(),
-            })
+            let empty_user = User {
+                fullname: String::new(),
+                workspaces: Vec::new(),
+                default_wid: 0,
+            };
+            let mut t = Toggl::with_client(credentials, client, api, empty_user);
+            match api {
+                ApiVersion::V8 => t.set_user_v8(resp.json()?),
+                ApiVersion::V9 => t.set_user_v9(resp.json()?)?,
+            }
+            Ok(t)
         } else {
-            Err(crate::error::TogglError::AuthError(
-                format!(
-                    "Authentication not succeded: Status {}, Text {}",
-                    resp.status(),
-                    resp.text().unwrap()
-                )
-                .to_owned(),
-            ))
+            Err(auth_error(resp))
+        }
+    }
+
+    /// The api token of the user, if it is known.
+    pub fn api_token(&self) -> Option<&str> {
+        self.api_token.as_deref()
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    fn sessions_url(&self) -> String {
+        match self.api {
+            ApiVersion::V8 => self.url("/sessions"),
+            ApiVersion::V9 => self.url("/me/sessions"),
+        }
+    }
+
+    /// Creates a session with the current credentials and uses its cookie for all further requests.
+    pub fn start_session(&mut self) -> Result<(), TogglError> {
+        let resp = self
+            .request(reqwest::Method::POST, &self.sessions_url())
+            .send()?;
+        if !resp.status().is_success() {
+            return Err(auth_error(resp));
+        }
+        let cookies = resp
+            .headers()
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| v.split(';').next())
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("; ");
+        if cookies.is_empty() {
+            return Err(TogglError::AuthError(
+                "The api did not return a session cookie".to_owned(),
+            ));
+        }
+        self.credentials = Credentials::Session(cookies);
+        Ok(())
+    }
+
+    /// Ends the session on the server. Does nothing if the requests are not authenticated with a session.
+    pub fn sign_out(self) -> Result<(), TogglError> {
+        if let Credentials::Session(_) = self.credentials {
+            self.request(reqwest::Method::DELETE, &self.sessions_url())
+                .send()?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the api token of the user with a new one, the old token stops working.
+    /// Returns the new token, which is also used for further requests if the old one was.
+    pub fn reset_api_token(&mut self) -> Result<String, TogglError> {
+        let path = match self.api {
+            ApiVersion::V8 => "/reset_token",
+            ApiVersion::V9 => "/me/reset_token",
+        };
+        let token: String = self
+            .request(reqwest::Method::POST, &self.url(path))
+            .send()?
+            .error_for_status()?
+            .json()?;
+        if let Credentials::ApiToken(_) = self.credentials {
+            self.credentials = Credentials::ApiToken(token.clone());
         }
+        self.api_token = Some(token.clone());
+        Ok(token)
     }
 }
+
+fn auth_error(mut resp: reqwest::Response) -> TogglError {
+    TogglError::AuthError(format!(
+        "Authentication not succeded: Status {}, Text {}",
+        resp.status(),
+        resp.text().unwrap_or_default()
+    ))
+}
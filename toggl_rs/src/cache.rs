@@ -0,0 +1,254 @@
+//! An on-disk cache of the user, workspaces and projects, so a `Toggl` can be created without querying the api.
+//!
+//! # Example
+//! ```no_run
+//! use std::path::Path;
+//! use toggl_rs::cache::MetadataCache;
+//! use toggl_rs::Toggl;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let path = Path::new("toggl_cache.json");
+//!     let mut toggl = match MetadataCache::load(path)? {
+//!         Some(cache) => {
+//!             let mut t = Toggl::from_cache("api_token", cache);
+//!             t.revalidate()?;
+//!             t
+//!         }
+//!         None => Toggl::init("api_token")?,
+//!     };
+//!     toggl.to_cache().store(path)?;
+//!     Ok(())
+//! }
+//! ```
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::error::TogglError;
+use crate::project::Project;
+use crate::user::User;
+use crate::workspace::Workspace;
+use crate::ApiVersion;
+use crate::Query;
+use crate::Toggl;
+
+/// The metadata held by `Toggl` in a form that can be stored.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MetadataCache {
+    /// Server timestamp of the last fetch, used to only query changes with `since`
+    pub since: i64,
+    /// When the data was last fetched or revalidated
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    pub fullname: String,
+    pub default_wid: i64,
+    pub workspaces: Vec<Workspace>,
+    pub projects: Vec<Project>,
+    /// Projects that are not listed, e.g., archived ones, which were fetched by id
+    #[serde(default)]
+    pub unlisted_projects: Vec<Project>,
+    /// Ids of projects that do not exist or we have no access to
+    #[serde(default)]
+    pub missing_projects: Vec<i64>,
+}
+
+impl MetadataCache {
+    /// Loads the cache from `path`, returns None if the file does not exist.
+    pub fn load(path: &Path) -> Result<Option<MetadataCache>, TogglError> {
+        match fs::read_to_string(path) {
+            Ok(s) => Ok(Some(serde_json::from_str(&s)?)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores the cache at `path`, creating parent directories if needed.
+    pub fn store(&self, path: &Path) -> Result<(), TogglError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The age of the cached data.
+    pub fn age(&self) -> chrono::Duration {
+        chrono::Utc::now() - self.fetched_at
+    }
+}
+
+/// Return of `/me` with related data, only including objects changed since the given timestamp
+#[derive(Deserialize, Debug)]
+struct ChangesResponse {
+    since: i64,
+    data: ChangedData,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangedData {
+    fullname: String,
+    default_wid: i64,
+    #[serde(default)]
+    workspaces: Vec<Workspace>,
+    #[serde(default)]
+    projects: Vec<ChangedProject>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangedProject {
+    #[serde(flatten)]
+    project: Project,
+    server_deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Toggl {
+    /// Creates a toggl object from cached data without querying the api.
+    /// Use `revalidate` to fetch the changes since the cache was written.
+    pub fn from_cache(api_token: &str, cache: MetadataCache) -> Toggl {
+        let user = User {
+            fullname: cache.fullname,
+            workspaces: cache.workspaces.into_iter().map(Rc::new).collect(),
+            default_wid: cache.default_wid,
+        };
+        let mut t = Toggl::from_parts(api_token.trim_end(), user, cache.projects);
+        {
+            let mut unlisted = t.unlisted_projects.borrow_mut();
+            for p in cache.unlisted_projects {
+                unlisted.insert(p.id, Some(Rc::new(p)));
+            }
+            for id in cache.missing_projects {
+                unlisted.insert(id, None);
+            }
+        }
+        t.since = cache.since;
+        t.loaded_at = cache.fetched_at;
+        t
+    }
+
+    /// Returns the loaded user, workspaces and projects to be stored, including the projects fetched by id.
+    pub fn to_cache(&self) -> MetadataCache {
+        let unlisted = self.unlisted_projects.borrow();
+        let mut unlisted_projects = unlisted
+            .values()
+            .flatten()
+            .map(|p| (**p).clone())
+            .collect::<Vec<_>>();
+        unlisted_projects.sort_by_key(|p| p.id);
+        let mut missing_projects = unlisted
+            .iter()
+            .filter(|(_, p)| p.is_none())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        missing_projects.sort_unstable();
+        MetadataCache {
+            since: self.since,
+            fetched_at: self.loaded_at,
+            fullname: self.user.fullname.clone(),
+            default_wid: self.user.default_wid,
+            workspaces: self.user.workspaces.iter().map(|w| (**w).clone()).collect(),
+            projects: self.projects.iter().map(|p| (**p).clone()).collect(),
+            unlisted_projects,
+            missing_projects,
+        }
+    }
+
+    /// Fetches the user, workspaces and projects that changed since they were loaded and updates them in place.
+    /// Api v9 has no way to query only changes, so everything is reloaded there.
+    pub fn revalidate(&mut self) -> Result<(), TogglError> {
+        if self.api == ApiVersion::V9 {
+            return self.refresh();
+        }
+        let url = reqwest::Url::parse_with_params(
+            &self.url("/me"),
+            &[
+                ("with_related_data", "true".to_string()),
+                ("since", self.since.to_string()),
+            ],
+        )
+        .expect("Error in parsing URL");
+        let changes: ChangesResponse = self.get(url)?;
+
+        self.user.fullname = changes.data.fullname;
+        self.user.default_wid = changes.data.default_wid;
+        for w in changes.data.workspaces {
+            let w = Rc::new(w);
+            match self.user.workspaces.iter_mut().find(|v| v.id == w.id) {
+                Some(v) => *v = w,
+                None => self.user.workspaces.push(w),
+            }
+        }
+        self.reindex();
+        for p in changes.data.projects {
+            if p.server_deleted_at.is_some() {
+                self.remove_project(p.project.id);
+            } else {
+                self.upsert_project(p.project);
+            }
+        }
+        self.since = changes.since;
+        self.loaded_at = chrono::Utc::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::ProjectTrait;
+
+    fn project(id: i64, active: bool) -> Project {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "wid": 1, "cid": null, "name": format!("Project {}", id), "billable": false, "active": active
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unlisted_and_missing_projects_are_cached() {
+        let cache = MetadataCache {
+            since: 1,
+            fetched_at: chrono::Utc::now(),
+            fullname: "Test".to_owned(),
+            default_wid: 1,
+            workspaces: vec![
+                serde_json::from_value(serde_json::json!({"id": 1, "name": "Work"})).unwrap(),
+            ],
+            projects: vec![project(2, true)],
+            unlisted_projects: vec![project(3, false)],
+            missing_projects: vec![4],
+        };
+        let t = Toggl::from_cache("token", cache);
+        // answered from the cache without querying the api
+        assert_eq!(
+            t.get_project(3).map(|p| p.name.clone()),
+            Some("Project 3".to_owned())
+        );
+        assert_eq!(t.get_project(4), None);
+
+        let cache = t.to_cache();
+        assert_eq!(
+            cache.projects.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(
+            cache
+                .unlisted_projects
+                .iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(cache.missing_projects, vec![4]);
+    }
+
+    #[test]
+    fn old_caches_without_unlisted_projects_load() {
+        let cache: MetadataCache = serde_json::from_value(serde_json::json!({
+            "since": 1, "fetched_at": "2020-03-02T10:00:00Z", "fullname": "Test", "default_wid": 1,
+            "workspaces": [], "projects": []
+        }))
+        .unwrap();
+        assert!(cache.unlisted_projects.is_empty());
+        assert!(cache.missing_projects.is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+use crate::error::TogglError;
+use crate::Query;
+use crate::Toggl;
+
+/// A client of a workspace, projects refer to it by `cid`.
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq, Serialize)]
+pub struct Client {
+    pub id: i64,
+    /// Workspace id the client belongs to
+    pub wid: i64,
+    pub name: String,
+    /// Fields of the api we do not model, e.g., the archived flag of the client
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+pub trait ClientTrait {
+    /// Loads the clients of all workspaces. They are not kept in the struct, as most calls do not need them.
+    fn get_clients(&self) -> Result<Vec<Client>, TogglError>;
+}
+
+impl ClientTrait for Toggl {
+    fn get_clients(&self) -> Result<Vec<Client>, TogglError> {
+        let mut clients = Vec::new();
+        for w in &self.user.workspaces {
+            let url = self.url(&format!("/workspaces/{}/clients", w.id));
+            // like projects, workspaces without clients return null
+            let res: Option<Vec<Client>> = self.get(&url)?;
+            clients.extend(res.unwrap_or_default());
+        }
+        Ok(clients)
+    }
+}
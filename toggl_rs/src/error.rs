@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::types::TimeEntry;
+
 /// Error Value
 #[derive(Debug)]
 pub enum TogglError {
@@ -7,6 +9,17 @@ pub enum TogglError {
     AuthError(String),
     /// Errors that come from reqwest throwing an error
     ReqwestError(reqwest::Error),
+    /// The entry was changed on the server since the local copy was fetched
+    ConflictError {
+        local: Box<TimeEntry>,
+        remote: Box<TimeEntry>,
+    },
+    /// The api returned something we did not expect
+    ApiError(String),
+    /// Errors from reading or writing local files
+    IoError(std::io::Error),
+    /// Errors from (de)serializing local data
+    JsonError(serde_json::Error),
     /// Dummy Type. Not used in the API
     NotImplemented,
 }
@@ -17,17 +30,47 @@ impl std::convert::From<reqwest::Error> for crate::error::TogglError {
     }
 }
 
+impl std::convert::From<std::io::Error> for crate::error::TogglError {
+    fn from(e: std::io::Error) -> crate::error::TogglError {
+        crate::error::TogglError::IoError(e)
+    }
+}
+
+impl std::convert::From<serde_json::Error> for crate::error::TogglError {
+    fn from(e: serde_json::Error) -> crate::error::TogglError {
+        crate::error::TogglError::JsonError(e)
+    }
+}
+
 impl std::convert::From<reqwest::header::InvalidHeaderValue> for crate::error::TogglError {
     fn from(_e: reqwest::header::InvalidHeaderValue) -> crate::error::TogglError {
         crate::error::TogglError::AuthError("Could not parse Authentication api_token".to_owned())
     }
 }
 
+impl TogglError {
+    /// Returns true if the api could not be reached at all, e.g., because the network is down.
+    pub fn is_offline(&self) -> bool {
+        match self {
+            TogglError::ReqwestError(e) => e.is_http() || e.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for TogglError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             TogglError::AuthError(e) => write!(f, "Authentication error: {}", e),
             TogglError::ReqwestError(e) => write!(f, "Reqwest error: {}", e),
+            TogglError::ConflictError { local, remote } => write!(
+                f,
+                "Conflict: time entry {} was changed on the server at {}, the local copy is from {}",
+                local.id, remote.at, local.at
+            ),
+            TogglError::ApiError(e) => write!(f, "API error: {}", e),
+            TogglError::IoError(e) => write!(f, "IO error: {}", e),
+            TogglError::JsonError(e) => write!(f, "JSON error: {}", e),
             TogglError::NotImplemented => write!(f, "An unexpected error occurred"),
         }
     }
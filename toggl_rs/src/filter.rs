@@ -0,0 +1,170 @@
+use crate::project::Project;
+use crate::types::TimeEntry;
+use crate::workspace::Workspace;
+use regex::Regex;
+
+/// A composable filter for time entries.
+/// The start and end date are sent to the api, all other criteria are applied to the fetched entries.
+///
+/// # Example
+/// ```no_run
+/// use toggl_rs::filter::EntryFilter;
+/// use toggl_rs::{Toggl, TogglExt};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let toggl = Toggl::init("api_token")?;
+///     let filter = EntryFilter::new()
+///         .start(chrono::Utc::now() - chrono::Duration::days(7))
+///         .project(&toggl.projects()[0])
+///         .tag("meeting")
+///         .billable(true);
+///
+///     for entry in toggl.get_time_entries_filtered(&filter)? {
+///         println!("{:?}", entry.description);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EntryFilter {
+    start: Option<chrono::DateTime<chrono::Utc>>,
+    end: Option<chrono::DateTime<chrono::Utc>>,
+    projects: Vec<Option<i64>>,
+    workspace: Option<i64>,
+    tags: Vec<String>,
+    excluded_tags: Vec<String>,
+    description: Option<Regex>,
+    billable: Option<bool>,
+    running: Option<bool>,
+    min_duration: Option<chrono::Duration>,
+    max_duration: Option<chrono::Duration>,
+}
+
+impl EntryFilter {
+    /// A filter that matches every entry.
+    pub fn new() -> Self {
+        EntryFilter::default()
+    }
+
+    /// Only entries starting at or after `start`. This is queried on the api.
+    pub fn start(mut self, start: chrono::DateTime<chrono::Utc>) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Only entries starting before `end`. This is queried on the api.
+    pub fn end(mut self, end: chrono::DateTime<chrono::Utc>) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Only entries of the given project. Can be called multiple times to match any of several projects.
+    pub fn project<T: AsRef<Project>>(mut self, p: T) -> Self {
+        self.projects.push(Some(p.as_ref().id));
+        self
+    }
+
+    /// Only entries of any of the given projects.
+    pub fn projects<T: AsRef<Project>, I: IntoIterator<Item = T>>(mut self, ps: I) -> Self {
+        self.projects
+            .extend(ps.into_iter().map(|p| Some(p.as_ref().id)));
+        self
+    }
+
+    /// Also match entries without a project when filtering by projects, or only those if no project is given.
+    pub fn without_project(mut self) -> Self {
+        self.projects.push(None);
+        self
+    }
+
+    /// Only entries in the given workspace.
+    pub fn workspace(mut self, w: &Workspace) -> Self {
+        self.workspace = Some(w.id);
+        self
+    }
+
+    /// Only entries that have the tag. If called multiple times, entries need to have all the tags.
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Only entries that do not have the tag.
+    pub fn exclude_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.excluded_tags.push(tag.into());
+        self
+    }
+
+    /// Only entries whose description matches the regex. Entries without description are matched as the empty string.
+    pub fn description(mut self, re: Regex) -> Self {
+        self.description = Some(re);
+        self
+    }
+
+    /// Only billable or only non billable entries.
+    pub fn billable(mut self, billable: bool) -> Self {
+        self.billable = Some(billable);
+        self
+    }
+
+    /// Only running or only finished entries.
+    pub fn running(mut self, running: bool) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    /// Only entries that took at least `d`. Running entries are measured until now.
+    pub fn min_duration(mut self, d: chrono::Duration) -> Self {
+        self.min_duration = Some(d);
+        self
+    }
+
+    /// Only entries that took at most `d`. Running entries are measured until now.
+    pub fn max_duration(mut self, d: chrono::Duration) -> Self {
+        self.max_duration = Some(d);
+        self
+    }
+
+    /// The start date that should be sent to the api.
+    pub fn start_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.start
+    }
+
+    /// The end date that should be sent to the api.
+    pub fn end_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.end
+    }
+
+    /// Returns true if the entry matches all criteria. `now` is used for the duration of running entries.
+    pub fn matches(&self, t: &TimeEntry, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let elapsed = t.elapsed(now);
+        self.start.map(|s| t.start >= s).unwrap_or(true)
+            && self.end.map(|e| t.start < e).unwrap_or(true)
+            && (self.projects.is_empty()
+                || self.projects.contains(&t.project_id()))
+            && self.workspace.map(|w| t.workspace.id == w).unwrap_or(true)
+            && self.tags.iter().all(|tag| t.tags.contains(tag))
+            && !self.excluded_tags.iter().any(|tag| t.tags.contains(tag))
+            && self
+                .description
+                .as_ref()
+                .map(|re| re.is_match(t.description.as_deref().unwrap_or("")))
+                .unwrap_or(true)
+            && self.billable.map(|b| t.billable == b).unwrap_or(true)
+            && self.running.map(|r| t.is_running() == r).unwrap_or(true)
+            && self.min_duration.map(|d| elapsed >= d).unwrap_or(true)
+            && self.max_duration.map(|d| elapsed <= d).unwrap_or(true)
+    }
+
+    /// Keeps only the entries that match the filter.
+    pub fn apply(
+        &self,
+        entries: Vec<TimeEntry>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<TimeEntry> {
+        entries
+            .into_iter()
+            .filter(|t| self.matches(t, now))
+            .collect()
+    }
+}
@@ -0,0 +1,442 @@
+//! A local journal of operations that could not be sent to the api, e.g., because the network is down.
+//! Entries started or created offline get a client-generated guid, so later operations can refer to them.
+//! The journal is replayed once the api is reachable again.
+//!
+//! # Example
+//! ```no_run
+//! use std::path::Path;
+//! use toggl_rs::journal::{Journal, Operation};
+//! use toggl_rs::{Toggl, TogglExt};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut journal = Journal::open(Path::new("journal.jsonl"))?;
+//!     let toggl = Toggl::init("api_token")?;
+//!     let description = Some("Offline work".to_string());
+//!     let project = toggl.projects().first();
+//!     if let Err(e) = toggl.start_entry(description.clone(), &[], project) {
+//!         if e.is_offline() {
+//!             let wid = toggl.user().default_wid;
+//!             journal.record(Operation::start(description, &[], project, wid))?;
+//!         }
+//!     }
+//!
+//!     // later, when we are online again
+//!     let report = journal.replay(&toggl)?;
+//!     println!("Applied {} operations", report.applied);
+//!     Ok(())
+//! }
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::TogglError;
+use crate::project::Project;
+use crate::time_entry::{history_start, EntryDetails, NewEntry, TimeEntryExt};
+use crate::types::{TimeEntry, TimeEntryInner};
+use crate::Toggl;
+
+/// Refers to the time entry an operation applies to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum EntryRef {
+    /// An entry known to the api
+    Id(i64),
+    /// An entry that was started or created offline
+    Guid(uuid::Uuid),
+    /// Whatever entry is running when the operation is replayed
+    Running,
+}
+
+/// An operation on time entries that is replayed later.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Operation {
+    /// Starts a running entry at `start`
+    Start {
+        guid: uuid::Uuid,
+        description: Option<String>,
+        tags: Vec<String>,
+        pid: Option<i64>,
+        wid: i64,
+        #[serde(default)]
+        tid: Option<i64>,
+        #[serde(default)]
+        billable: Option<bool>,
+        start: chrono::DateTime<chrono::Utc>,
+    },
+    /// Stops the entry at `stop`
+    Stop {
+        entry: EntryRef,
+        stop: chrono::DateTime<chrono::Utc>,
+    },
+    /// Creates a finished entry
+    Create {
+        guid: uuid::Uuid,
+        description: Option<String>,
+        tags: Vec<String>,
+        pid: Option<i64>,
+        wid: i64,
+        #[serde(default)]
+        tid: Option<i64>,
+        #[serde(default)]
+        billable: Option<bool>,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: i64,
+    },
+    /// Replaces the entry with all values of `values`
+    Update {
+        entry: EntryRef,
+        values: TimeEntryInner,
+    },
+    Delete {
+        entry: EntryRef,
+    },
+}
+
+impl Operation {
+    /// Starts an entry now with a new guid in the workspace of the project or `wid`.
+    pub fn start<T: AsRef<Project>>(
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        wid: i64,
+    ) -> Operation {
+        Operation::start_with(description, tags, p, &EntryDetails::default(), wid)
+    }
+
+    /// Starts an entry like `TimeEntryExt::start_entry_with`, `default_wid` is used if neither `details` nor the project give a workspace.
+    pub fn start_with<T: AsRef<Project>>(
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+        default_wid: i64,
+    ) -> Operation {
+        let p = p.as_ref().map(|v| v.as_ref());
+        Operation::Start {
+            guid: uuid::Uuid::new_v4(),
+            description,
+            tags: tags.to_owned(),
+            pid: p.map(|v| v.id),
+            wid: details.wid(p, default_wid),
+            tid: details.task,
+            billable: details.billable,
+            start: chrono::Utc::now(),
+        }
+    }
+
+    /// Stops the running entry now.
+    pub fn stop_running() -> Operation {
+        Operation::Stop {
+            entry: EntryRef::Running,
+            stop: chrono::Utc::now(),
+        }
+    }
+
+    /// Creates a finished entry with a new guid in the workspace of the project or `wid`.
+    pub fn create<T: AsRef<Project>>(
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        wid: i64,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Operation {
+        Operation::create_with(
+            description,
+            tags,
+            p,
+            &EntryDetails::default(),
+            wid,
+            start,
+            duration,
+        )
+    }
+
+    /// Creates a finished entry like `TimeEntryExt::create_entry_with`, `default_wid` is used if neither `details` nor the project give a workspace.
+    pub fn create_with<T: AsRef<Project>>(
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+        default_wid: i64,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Operation {
+        let p = p.as_ref().map(|v| v.as_ref());
+        Operation::Create {
+            guid: uuid::Uuid::new_v4(),
+            description,
+            tags: tags.to_owned(),
+            pid: p.map(|v| v.id),
+            wid: details.wid(p, default_wid),
+            tid: details.task,
+            billable: details.billable,
+            start,
+            duration: duration.num_seconds(),
+        }
+    }
+
+    /// Updates the entry with all its values.
+    pub fn update(t: TimeEntry) -> Operation {
+        Operation::Update {
+            entry: EntryRef::Id(t.id),
+            values: t.into(),
+        }
+    }
+
+    /// Deletes the entry.
+    pub fn delete(t: &TimeEntry) -> Operation {
+        Operation::Delete {
+            entry: EntryRef::Id(t.id),
+        }
+    }
+}
+
+/// An operation together with the time it was recorded, which is compared to the `at` of the entry on the server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JournalEntry {
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub operation: Operation,
+}
+
+/// The result of a replay.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Number of operations that were sent to the api
+    pub applied: usize,
+    /// Operations that were dropped because the entry was changed on the server after the operation was recorded,
+    /// or because the entry does not exist anymore
+    pub conflicts: Vec<JournalEntry>,
+    /// Operations that were dropped because the api rejected them, e.g., because the project was archived, with the error
+    pub failed: Vec<(JournalEntry, String)>,
+}
+
+/// The local journal, stored as one json operation per line.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, a missing file is an empty journal.
+    pub fn open(path: &Path) -> Result<Journal, TogglError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(s) => s
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<JournalEntry>, _>>()?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Journal {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    /// The operations that still have to be replayed.
+    pub fn pending(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records the operation and appends it to the journal file.
+    pub fn record(&mut self, operation: Operation) -> Result<(), TogglError> {
+        let entry = JournalEntry {
+            recorded_at: chrono::Utc::now(),
+            operation,
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Returns the guid of the entry that was started offline and is still running, if any.
+    pub fn running_offline_entry(&self) -> Option<uuid::Uuid> {
+        let mut running = None;
+        for e in &self.entries {
+            match e.operation {
+                Operation::Start { guid, .. } => running = Some(guid),
+                Operation::Stop { .. } => running = None,
+                _ => {}
+            }
+        }
+        running
+    }
+
+    /// Sends all operations to the api in the order they were recorded.
+    /// Operations on entries that were changed on the server after they were recorded are dropped and reported as conflicts.
+    /// Operations the api rejects are dropped and reported as failed, so they do not block the rest of the journal.
+    /// If the api can not be reached, the remaining operations are kept in the journal.
+    /// The journal is rewritten after every operation, so an interrupted replay does not send operations twice.
+    pub fn replay(&mut self, t: &Toggl) -> Result<ReplayReport, TogglError> {
+        let mut report = ReplayReport::default();
+        let mut state = ReplayState::default();
+        while !self.entries.is_empty() {
+            match self.apply(t, &self.entries[0], &mut state) {
+                Ok(true) => report.applied += 1,
+                Ok(false) => report.conflicts.push(self.entries[0].clone()),
+                Err(e) if e.is_offline() => return Err(e),
+                Err(e) => report.failed.push((self.entries[0].clone(), e.to_string())),
+            }
+            self.entries.remove(0);
+            self.write()?;
+        }
+        Ok(report)
+    }
+
+    /// Rewrites the journal file with the pending operations.
+    fn write(&self) -> Result<(), TogglError> {
+        if self.entries.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                r => r.map_err(|e| e.into()),
+            };
+        }
+        let mut s = String::new();
+        for e in &self.entries {
+            s.push_str(&serde_json::to_string(e)?);
+            s.push('\n');
+        }
+        fs::write(&self.path, s)?;
+        Ok(())
+    }
+
+    /// Applies a single operation, returns false if it was dropped because of a conflict.
+    fn apply(
+        &self,
+        t: &Toggl,
+        e: &JournalEntry,
+        state: &mut ReplayState,
+    ) -> Result<bool, TogglError> {
+        match e.operation {
+            Operation::Start {
+                guid,
+                ref description,
+                ref tags,
+                pid,
+                wid,
+                tid,
+                billable,
+                start,
+            } => {
+                let mut new = NewEntry::new(description.clone(), tags, pid, wid);
+                new.tid = tid;
+                new.billable = billable;
+                new.start = Some(start);
+                let id = create(t, guid, new)?;
+                state.created(guid, id);
+                Ok(true)
+            }
+            Operation::Create {
+                guid,
+                ref description,
+                ref tags,
+                pid,
+                wid,
+                tid,
+                billable,
+                start,
+                duration,
+            } => {
+                let mut new = NewEntry::new(description.clone(), tags, pid, wid);
+                new.tid = tid;
+                new.billable = billable;
+                new.start = Some(start);
+                new.duration = Some(duration);
+                let id = create(t, guid, new)?;
+                state.created(guid, id);
+                Ok(true)
+            }
+            Operation::Stop { ref entry, stop } => match state.resolve(t, entry, e.recorded_at)? {
+                Some(mut current) if current.is_running() => {
+                    state.touched.insert(current.id);
+                    current.stop = Some(stop);
+                    current.duration = (stop - current.start).num_seconds();
+                    t.update_entry(current)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            Operation::Update {
+                ref entry,
+                ref values,
+            } => match state.resolve(t, entry, e.recorded_at)? {
+                Some(current) => {
+                    state.touched.insert(current.id);
+                    let mut values = values.clone();
+                    values.id = current.id;
+                    t.put_entry(values)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            Operation::Delete { ref entry } => match state.resolve(t, entry, e.recorded_at)? {
+                Some(current) => {
+                    t.delete_entry(&current)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+        }
+    }
+}
+
+/// Creates the entry with the client-generated guid and returns its id, a `duration` of None starts it running.
+fn create(t: &Toggl, guid: uuid::Uuid, mut e: NewEntry) -> Result<i64, TogglError> {
+    e.guid = Some(guid);
+    t.post_entry(&e)
+}
+
+/// What happened during a replay so far.
+#[derive(Default)]
+struct ReplayState {
+    /// Ids of the entries created from guids
+    ids: HashMap<uuid::Uuid, i64>,
+    /// Entries changed by the replay, their `at` is newer than any recorded operation
+    touched: HashSet<i64>,
+}
+
+impl ReplayState {
+    fn created(&mut self, guid: uuid::Uuid, id: i64) {
+        self.ids.insert(guid, id);
+        self.touched.insert(id);
+    }
+
+    /// Finds the entry on the server. Returns None if it does not exist or was changed on the server after `recorded_at`.
+    fn resolve(
+        &self,
+        t: &Toggl,
+        entry: &EntryRef,
+        recorded_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<TimeEntry>, TogglError> {
+        let current = match entry {
+            EntryRef::Id(id) => t.get_entry_details(*id)?,
+            EntryRef::Guid(guid) => match self.ids.get(guid) {
+                Some(id) => t.get_entry_details(*id)?,
+                None => {
+                    // the entry was created by an earlier, interrupted replay, i.e., after the operation was recorded
+                    let from =
+                        std::cmp::max(recorded_at - chrono::Duration::days(2), history_start());
+                    let entries = t.get_time_entries_range(Some(from), None)?;
+                    return Ok(entries.into_iter().find(|v| v.guid == *guid));
+                }
+            },
+            EntryRef::Running => t.get_running_entry()?,
+        };
+        Ok(current.filter(|v| self.touched.contains(&v.id) || v.at <= recorded_at))
+    }
+}
@@ -1,5 +1,6 @@
-//! This is a library to interact with the toggl.com api version 8..
+//! This is a library to interact with the toggl.com api version 8 and 9.
 //! Your main interaction with the api will be the Toggl struct. Methods are in the TogglExt trait including the main objects being `Project` and `TimeEntry`.
+//! The api version is chosen when creating the struct (see `ApiVersion`), all methods and the resolved model are the same for both.
 //!
 //! # Example
 //! ```no_run
@@ -10,7 +11,7 @@
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let toggl = Toggl::init(API_TOKEN)?;
-//!     let project = toggl.projects[0].clone();
+//!     let project = toggl.projects()[0].clone();
 //!
 //!     let _ = toggl.start_entry(Some(String::from("test")), &[], Some(project))?;
 //!
@@ -28,41 +29,256 @@ extern crate serde_derive;
 extern crate uuid;
 
 use reqwest::IntoUrl;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::Duration;
 
 mod auth;
+pub mod cache;
+pub mod client;
 mod error;
+pub mod filter;
+pub mod journal;
 pub mod project;
+pub mod summary;
+pub mod tag;
+pub mod task;
 pub mod time_entry;
 mod types;
-mod user;
-mod workspace;
+pub mod user;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+pub mod workspace;
 
+pub use crate::auth::Credentials;
 pub use crate::error::TogglError;
 use crate::project::ProjectTrait;
 pub use crate::time_entry::TimeEntryExt as TogglExt;
-pub use crate::types::TimeEntry;
+pub use crate::types::{ProjectState, TimeEntry, TimeEntryInner};
 
 /// Call this to get a toggl object on which you can call various methods.
 /// This will be hour handler to the api.
 /// Notice, that this will already query the api.
 impl Toggl {
     pub fn init(api_token: &str) -> Result<Toggl, crate::error::TogglError> {
-        let mut t = Toggl::authenticate_api_token(api_token)?;
-        t.fill_projects();
+        Toggl::init_with_version(api_token, ApiVersion::V8)
+    }
+
+    /// Like `init` but uses the given version of the api.
+    pub fn init_with_version(
+        api_token: &str,
+        api: ApiVersion,
+    ) -> Result<Toggl, crate::error::TogglError> {
+        let mut t = Toggl::authenticate_api_token_with_version(api_token, api)?;
+        t.refresh_projects()?;
         Ok(t)
     }
 }
 
+/// The version of the Toggl api that is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// The deprecated `/api/v8`
+    #[default]
+    V8,
+    /// `/api/v9`, which needs the workspace in most paths and returns objects without a `data` wrapper
+    V9,
+}
+
+impl ApiVersion {
+    fn base_url(self) -> &'static str {
+        match self {
+            ApiVersion::V8 => "https://www.toggl.com/api/v8",
+            ApiVersion::V9 => "https://api.track.toggl.com/api/v9",
+        }
+    }
+}
+
 #[derive(Debug)]
 /// The main struct to interact with.
 pub struct Toggl {
-    api_token: String,
+    /// How requests are authenticated
+    credentials: Credentials,
+    /// The api token of the user, also known when authenticating with a password
+    api_token: Option<String>,
     client: reqwest::Client,
+    api: ApiVersion,
+    user: crate::user::User,
+    projects: Vec<Rc<crate::project::Project>>,
+    /// Lookup tables by id, these are rebuilt whenever the projects or workspaces change.
+    project_index: HashMap<i64, Rc<crate::project::Project>>,
+    workspace_index: HashMap<i64, Rc<crate::workspace::Workspace>>,
+    /// Projects that are not in `projects` (e.g., archived ones) and were fetched on demand.
+    /// None marks projects that do not exist or we have no access to, so we do not query them again.
+    unlisted_projects: RefCell<HashMap<i64, Option<Rc<crate::project::Project>>>>,
+    /// When the user and projects were last loaded from the api, for cached data when the cache was fetched.
+    loaded_at: chrono::DateTime<chrono::Utc>,
+    /// How long the loaded user and projects are considered fresh, None means forever.
+    cache_ttl: Option<Duration>,
+    /// Server timestamp of the last load of the user, used to only query changes.
+    since: i64,
+}
+
+impl Toggl {
+    /// Creates a toggl object from already known data without querying the api.
+    pub fn from_parts(
+        api_token: &str,
+        user: crate::user::User,
+        projects: Vec<crate::project::Project>,
+    ) -> Toggl {
+        let mut t = Toggl::with_client(
+            Credentials::ApiToken(api_token.to_owned()),
+            reqwest::Client::new(),
+            ApiVersion::V8,
+            user,
+        );
+        t.set_projects(projects.into_iter().map(Rc::new).collect());
+        t
+    }
+
+    fn with_client(
+        credentials: Credentials,
+        client: reqwest::Client,
+        api: ApiVersion,
+        user: crate::user::User,
+    ) -> Toggl {
+        let api_token = match credentials {
+            Credentials::ApiToken(ref v) => Some(v.clone()),
+            _ => None,
+        };
+        let mut t = Toggl {
+            credentials,
+            api_token,
+            client,
+            api,
+            user,
+            projects: Vec::new(),
+            project_index: HashMap::new(),
+            workspace_index: HashMap::new(),
+            unlisted_projects: RefCell::new(HashMap::new()),
+            loaded_at: chrono::Utc::now(),
+            cache_ttl: None,
+            since: 0,
+        };
+        t.reindex();
+        t
+    }
+
+    /// Sets the api version used for all further requests, e.g., after creating the struct with `from_parts`.
+    pub fn set_api_version(&mut self, api: ApiVersion) {
+        self.api = api;
+    }
+
+    pub fn api_version(&self) -> ApiVersion {
+        self.api
+    }
+
+    /// The full url of `path` for the used api version.
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.api.base_url(), path)
+    }
+
+    /// A request with the credentials applied.
+    fn request<U: IntoUrl>(&self, method: reqwest::Method, url: U) -> reqwest::RequestBuilder {
+        self.credentials.apply(self.client.request(method, url))
+    }
+
     /// Information of the user.
-    pub user: crate::user::User,
+    pub fn user(&self) -> &crate::user::User {
+        &self.user
+    }
+
     /// A handler to all projects currently available in Toggl.
-    pub projects: Vec<Rc<crate::project::Project>>,
+    pub fn projects(&self) -> &[Rc<crate::project::Project>] {
+        &self.projects
+    }
+
+    /// Returns the project with the given id if it is loaded.
+    /// Use `ProjectTrait::get_project` to also fetch projects that are not loaded.
+    pub fn project(&self, id: i64) -> Option<&Rc<crate::project::Project>> {
+        self.project_index.get(&id)
+    }
+
+    /// Returns the workspace with the given id if the user has access to it.
+    pub fn workspace(&self, id: i64) -> Option<&Rc<crate::workspace::Workspace>> {
+        self.workspace_index.get(&id)
+    }
+
+    /// Sets how long the loaded user and projects are considered fresh, see `refresh_if_stale`.
+    /// None (the default) means they are never refreshed automatically.
+    pub fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Returns true if the loaded user and projects are older than the cache ttl.
+    pub fn is_stale(&self) -> bool {
+        self.cache_ttl
+            .map(|ttl| {
+                // a ttl too large for chrono is never reached
+                chrono::Duration::from_std(ttl)
+                    .map(|ttl| chrono::Utc::now() - self.loaded_at >= ttl)
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Reloads the user and all projects from the api.
+    pub fn refresh(&mut self) -> Result<(), crate::error::TogglError> {
+        self.refresh_user()?;
+        self.refresh_projects()?;
+        self.loaded_at = chrono::Utc::now();
+        Ok(())
+    }
+
+    /// Reloads the user and all projects if they are older than the cache ttl. Returns true if they were reloaded.
+    pub fn refresh_if_stale(&mut self) -> Result<bool, crate::error::TogglError> {
+        if self.is_stale() {
+            self.refresh()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn set_projects(&mut self, projects: Vec<Rc<crate::project::Project>>) {
+        self.projects = projects;
+        self.unlisted_projects.borrow_mut().clear();
+        self.reindex();
+    }
+
+    /// Inserts or replaces the project in the loaded projects. Archived projects are kept with the unlisted ones.
+    fn upsert_project(&mut self, p: crate::project::Project) -> Rc<crate::project::Project> {
+        let p = Rc::new(p);
+        self.projects.retain(|v| v.id != p.id);
+        self.project_index.remove(&p.id);
+        if p.active {
+            self.projects.push(p.clone());
+            self.project_index.insert(p.id, p.clone());
+            self.unlisted_projects.borrow_mut().remove(&p.id);
+        } else {
+            self.unlisted_projects
+                .borrow_mut()
+                .insert(p.id, Some(p.clone()));
+        }
+        p
+    }
+
+    fn remove_project(&mut self, id: i64) {
+        self.projects.retain(|v| v.id != id);
+        self.project_index.remove(&id);
+        self.unlisted_projects.borrow_mut().insert(id, None);
+    }
+
+    fn reindex(&mut self) {
+        self.project_index = self.projects.iter().map(|p| (p.id, p.clone())).collect();
+        self.workspace_index = self
+            .user
+            .workspaces
+            .iter()
+            .map(|w| (w.id, w.clone()))
+            .collect();
+    }
 }
 
 trait Query {
@@ -80,10 +296,17 @@ trait Query {
         url: U,
         t: &T,
     ) -> Result<S, crate::error::TogglError>;
+    fn patch<U: IntoUrl, T: serde::ser::Serialize, S: serde::de::DeserializeOwned>(
+        &self,
+        url: U,
+        t: &T,
+    ) -> Result<S, crate::error::TogglError>;
     fn delete<U: IntoUrl, S: serde::de::DeserializeOwned>(
         &self,
         url: U,
     ) -> Result<S, crate::error::TogglError>;
+    /// Deletes without expecting a body in the response
+    fn delete_empty<U: IntoUrl>(&self, url: U) -> Result<(), crate::error::TogglError>;
 }
 
 impl Query for Toggl {
@@ -91,11 +314,7 @@ impl Query for Toggl {
         &self,
         url: U,
     ) -> Result<T, crate::error::TogglError> {
-        let mut resp = self
-            .client
-            .get(url)
-            .basic_auth(&self.api_token, Some("api_token"))
-            .send()?;
+        let mut resp = self.request(reqwest::Method::GET, url).send()?;
         Ok(resp.json()?)
     }
 
@@ -104,10 +323,8 @@ impl Query for Toggl {
         url: U,
         t: &T,
     ) -> Result<S, crate::error::TogglError> {
-        self.client
-            .post(url)
+        self.request(reqwest::Method::POST, url)
             .json(t)
-            .basic_auth(&self.api_token, Some("api_token"))
             .send()
             .and_then(|mut v| v.json::<S>())
             .map_err(|v| v.into())
@@ -118,10 +335,20 @@ impl Query for Toggl {
         url: U,
         t: &T,
     ) -> Result<S, crate::error::TogglError> {
-        self.client
-            .put(url)
+        self.request(reqwest::Method::PUT, url)
+            .json(t)
+            .send()
+            .and_then(|mut v| v.json())
+            .map_err(|e| e.into())
+    }
+
+    fn patch<U: IntoUrl, T: serde::ser::Serialize, S: serde::de::DeserializeOwned>(
+        &self,
+        url: U,
+        t: &T,
+    ) -> Result<S, crate::error::TogglError> {
+        self.request(reqwest::Method::PATCH, url)
             .json(t)
-            .basic_auth(&self.api_token, Some("api_token"))
             .send()
             .and_then(|mut v| v.json())
             .map_err(|e| e.into())
@@ -131,11 +358,17 @@ impl Query for Toggl {
         &self,
         url: U,
     ) -> Result<S, crate::error::TogglError> {
-        self.client
-            .delete(url)
-            .basic_auth(&self.api_token, Some("api_token"))
+        self.request(reqwest::Method::DELETE, url)
             .send()
             .and_then(|mut v| v.json())
             .map_err(|e| e.into())
     }
+
+    fn delete_empty<U: IntoUrl>(&self, url: U) -> Result<(), crate::error::TogglError> {
+        self.request(reqwest::Method::DELETE, url)
+            .send()
+            .and_then(|v| v.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.into())
+    }
 }
@@ -1,33 +1,160 @@
 use std::rc::Rc;
 
+use crate::error::TogglError;
+use crate::types::Return;
+use crate::workspace::Workspace;
+use crate::ApiVersion;
 use crate::Query;
 use crate::Toggl;
 
 pub type Projects = Vec<Rc<Project>>;
 
-#[derive(Deserialize, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq, Serialize)]
 pub struct Project {
     pub id: i64,
+    /// Workspace id the project belongs to
+    pub wid: i64,
+    /// Client id
+    pub cid: Option<i64>,
     pub name: String,
     pub billable: bool,
     pub active: bool,
+    /// All fields we do not model, so newer attributes of the api are not lost on updates
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+struct ProjectUpdate<'a> {
+    project: &'a Project,
+}
+
+#[derive(Serialize, Debug)]
+struct CreateProject {
+    project: NewProject,
+}
+
+#[derive(Serialize, Debug)]
+struct NewProject {
+    name: String,
+    wid: i64,
+    active: bool,
 }
 
 pub trait ProjectTrait {
+    /// Loads all projects of all workspaces. Panics if the api can not be reached, see `refresh_projects`.
     fn fill_projects(&mut self);
+
+    /// Reloads all active projects of all workspaces from the api.
+    fn refresh_projects(&mut self) -> Result<(), TogglError>;
+
+    /// Returns the project with the given id.
+    /// Projects that are not loaded, e.g., because they are archived, are fetched from the api and remembered.
+    /// Returns None if the project does not exist (anymore) or we have no access to it.
+    fn get_project(&self, id: i64) -> Option<Rc<Project>>;
+
+    /// Creates a project with the given name in the workspace and adds it to the loaded projects.
+    fn create_project(&mut self, name: &str, w: &Workspace) -> Result<Rc<Project>, TogglError>;
+
+    /// Updates the project with all values in `p` and replaces the loaded project.
+    fn update_project(&mut self, p: &Project) -> Result<Rc<Project>, TogglError>;
 }
 
 impl ProjectTrait for Toggl {
     fn fill_projects(&mut self) {
-        self.projects = self
-            .user
-            .workspaces
-            .iter()
-            .flat_map(|w| {
-                let url = format!("https://www.toggl.com/api/v8/workspaces/{}/projects", w.id);
-                let res: Vec<Project> = self.get(&url).expect("Error in querying");
-                res.into_iter().map(Rc::new)
-            })
-            .collect();
+        self.refresh_projects().expect("Error in querying");
+    }
+
+    fn refresh_projects(&mut self) -> Result<(), TogglError> {
+        let mut projects = Vec::new();
+        for w in &self.user.workspaces {
+            let url = self.url(&format!("/workspaces/{}/projects", w.id));
+            // the api returns null instead of an empty list for workspaces without projects
+            let res: Option<Vec<Project>> = self.get(&url)?;
+            projects.extend(res.unwrap_or_default().into_iter().map(Rc::new));
+        }
+        self.set_projects(projects);
+        Ok(())
+    }
+
+    fn get_project(&self, id: i64) -> Option<Rc<Project>> {
+        if let Some(p) = self.project(id) {
+            return Some(p.clone());
+        }
+        if let Some(p) = self.unlisted_projects.borrow().get(&id) {
+            return p.clone();
+        }
+        let fetched = match self.api {
+            ApiVersion::V8 => self
+                .fetch_project::<Return<Project>>(&self.url(&format!("/projects/{}", id)))
+                .map(|r| r.map(|v| Rc::new(v.data))),
+            // projects can only be queried per workspace in v9
+            ApiVersion::V9 => self
+                .user
+                .workspaces
+                .iter()
+                .map(|w| {
+                    self.fetch_project::<Project>(
+                        &self.url(&format!("/workspaces/{}/projects/{}", w.id, id)),
+                    )
+                })
+                .find_map(|r| r.transpose())
+                .transpose()
+                .map(|p| p.map(Rc::new)),
+        };
+        // only a missing project is remembered, e.g., a timeout is tried again on the next call
+        let p = fetched.ok()?;
+        self.unlisted_projects.borrow_mut().insert(id, p.clone());
+        p
+    }
+
+    fn create_project(&mut self, name: &str, w: &Workspace) -> Result<Rc<Project>, TogglError> {
+        let p = NewProject {
+            name: name.to_owned(),
+            wid: w.id,
+            active: true,
+        };
+        let res = match self.api {
+            ApiVersion::V8 => {
+                self.post::<&str, CreateProject, Return<Project>>(
+                    &self.url("/projects"),
+                    &CreateProject { project: p },
+                )?
+                .data
+            }
+            ApiVersion::V9 => self.post(&self.url(&format!("/workspaces/{}/projects", w.id)), &p)?,
+        };
+        Ok(self.upsert_project(res))
+    }
+
+    fn update_project(&mut self, p: &Project) -> Result<Rc<Project>, TogglError> {
+        let res = match self.api {
+            ApiVersion::V8 => {
+                self.put::<&str, ProjectUpdate, Return<Project>>(
+                    &self.url(&format!("/projects/{}", p.id)),
+                    &ProjectUpdate { project: p },
+                )?
+                .data
+            }
+            ApiVersion::V9 => self.put(
+                &self.url(&format!("/workspaces/{}/projects/{}", p.wid, p.id)),
+                p,
+            )?,
+        };
+        Ok(self.upsert_project(res))
+    }
+}
+
+impl Toggl {
+    /// Fetches a single project, None if it does not exist or we have no access to it.
+    fn fetch_project<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Result<Option<T>, TogglError> {
+        let res = self.request(reqwest::Method::GET, url).send()?;
+        match res.status() {
+            reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::NOT_FOUND => Ok(None),
+            _ => Ok(Some(res.error_for_status()?.json()?)),
+        }
     }
 }
@@ -0,0 +1,404 @@
+//! Grouping and summing of time entries, so every frontend uses the same math.
+//!
+//! # Example
+//! ```no_run
+//! use toggl_rs::summary::{self, Grouping};
+//! use toggl_rs::{Toggl, TogglExt};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let toggl = Toggl::init("api_token")?;
+//!     let entries = toggl.get_time_entries()?;
+//!     let now = chrono::Utc::now();
+//!
+//!     for group in summary::group_by(&entries, Grouping::Project, &chrono::Local, now) {
+//!         println!("{}: {} minutes", group.key, group.totals.total.num_minutes());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use chrono::Datelike;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::project::Project;
+use crate::types::TimeEntry;
+
+/// The attribute entries are grouped by.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum Grouping {
+    Project,
+    /// Groups by the client of the project.
+    Client,
+    /// An entry with several tags is counted for each of them.
+    Tag,
+    /// Entries that cross midnight are split between the days.
+    Day,
+    /// Weeks start at the given weekday, entries that cross the week boundary are split.
+    Week(chrono::Weekday),
+    Description,
+}
+
+/// The key of a group. `None` is the bucket for entries that do not have the attribute.
+/// Serializes as an object with the variant as the only key, e.g., `{"Tag": "meeting"}` or `{"Client": null}`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum GroupKey {
+    /// Entries are grouped by the project id, so entries whose project could not be loaded are not mixed up with entries without a project.
+    /// `project` is the project of one of the entries if it was loaded, for display.
+    Project {
+        id: Option<i64>,
+        project: Option<Rc<Project>>,
+    },
+    /// Client id
+    Client(Option<i64>),
+    Tag(Option<String>),
+    Day(chrono::NaiveDate),
+    /// The first day of the week
+    Week(chrono::NaiveDate),
+    Description(Option<String>),
+}
+
+impl GroupKey {
+    /// Whether entries with the keys belong to the same group, projects are compared by id only.
+    fn same_group(&self, other: &GroupKey) -> bool {
+        match (self, other) {
+            (GroupKey::Project { id: a, .. }, GroupKey::Project { id: b, .. }) => a == b,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupKey::Project {
+                project: Some(p), ..
+            } => write!(f, "{}", p.name),
+            GroupKey::Project { id: Some(id), .. } => write!(f, "Project {}", id),
+            GroupKey::Project { id: None, .. } => write!(f, "No Project"),
+            GroupKey::Client(Some(c)) => write!(f, "Client {}", c),
+            GroupKey::Client(None) => write!(f, "No Client"),
+            GroupKey::Tag(Some(t)) => write!(f, "{}", t),
+            GroupKey::Tag(None) => write!(f, "No Tag"),
+            GroupKey::Day(d) => write!(f, "{}", d),
+            GroupKey::Week(d) => write!(f, "Week of {}", d),
+            GroupKey::Description(Some(d)) => write!(f, "{}", d),
+            GroupKey::Description(None) => write!(f, "No Description"),
+        }
+    }
+}
+
+/// Summed up durations of a number of entries. Durations are serialized as seconds.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    #[serde(with = "seconds")]
+    pub total: chrono::Duration,
+    #[serde(with = "seconds")]
+    pub billable: chrono::Duration,
+    #[serde(with = "seconds")]
+    pub non_billable: chrono::Duration,
+    /// Number of entries that contributed to the totals
+    pub entries: usize,
+}
+
+impl Default for Totals {
+    fn default() -> Self {
+        Totals {
+            total: chrono::Duration::zero(),
+            billable: chrono::Duration::zero(),
+            non_billable: chrono::Duration::zero(),
+            entries: 0,
+        }
+    }
+}
+
+impl Totals {
+    fn add(&mut self, d: chrono::Duration, billable: bool) {
+        self.total += d;
+        if billable {
+            self.billable += d;
+        } else {
+            self.non_billable += d;
+        }
+        self.entries += 1;
+    }
+
+    /// The share of these totals of `of` as a fraction between 0 and 1, zero if `of` is empty.
+    pub fn share(&self, of: &Totals) -> f64 {
+        if of.total.num_seconds() == 0 {
+            0.0
+        } else {
+            self.total.num_seconds() as f64 / of.total.num_seconds() as f64
+        }
+    }
+}
+
+/// A group of entries with the same key.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Group {
+    pub key: GroupKey,
+    pub totals: Totals,
+}
+
+/// Sums up all entries. Running entries are counted until `now`.
+pub fn totals(entries: &[TimeEntry], now: chrono::DateTime<chrono::Utc>) -> Totals {
+    let mut t = Totals::default();
+    for e in entries {
+        t.add(e.elapsed(now), e.billable);
+    }
+    t
+}
+
+/// Groups the entries by `grouping` and sums them up. Days and weeks are computed in the timezone `tz`.
+/// Groups are returned in the order they first appear, days and weeks are sorted chronologically.
+pub fn group_by<Tz: chrono::TimeZone>(
+    entries: &[TimeEntry],
+    grouping: Grouping,
+    tz: &Tz,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut add = |key: GroupKey, d: chrono::Duration, billable: bool| {
+        if let Some(g) = groups.iter_mut().find(|g| g.key.same_group(&key)) {
+            g.totals.add(d, billable);
+            if let (
+                GroupKey::Project {
+                    project: p @ None, ..
+                },
+                GroupKey::Project { project, .. },
+            ) = (&mut g.key, key)
+            {
+                *p = project;
+            }
+        } else {
+            let mut totals = Totals::default();
+            totals.add(d, billable);
+            groups.push(Group { key, totals });
+        }
+    };
+
+    for e in entries {
+        let elapsed = e.elapsed(now);
+        match grouping {
+            Grouping::Project => add(
+                GroupKey::Project {
+                    id: e.project_id(),
+                    project: e.project.clone(),
+                },
+                elapsed,
+                e.billable,
+            ),
+            Grouping::Client => add(
+                GroupKey::Client(e.project.as_ref().and_then(|p| p.cid)),
+                elapsed,
+                e.billable,
+            ),
+            Grouping::Tag if e.tags.is_empty() => add(GroupKey::Tag(None), elapsed, e.billable),
+            Grouping::Tag => {
+                for t in &e.tags {
+                    add(GroupKey::Tag(Some(t.clone())), elapsed, e.billable);
+                }
+            }
+            Grouping::Day => {
+                for day in days(e, tz, now) {
+                    add(GroupKey::Day(day), e.elapsed_on(day, tz, now), e.billable);
+                }
+            }
+            Grouping::Week(week_start) => {
+                let mut weeks: Vec<(chrono::NaiveDate, chrono::Duration)> = Vec::new();
+                for day in days(e, tz, now) {
+                    let first = first_day_of_week(day, week_start);
+                    let d = e.elapsed_on(day, tz, now);
+                    match weeks.iter_mut().find(|w| w.0 == first) {
+                        Some(w) => w.1 += d,
+                        None => weeks.push((first, d)),
+                    }
+                }
+                for (first, d) in weeks {
+                    add(GroupKey::Week(first), d, e.billable);
+                }
+            }
+            Grouping::Description => add(
+                GroupKey::Description(e.description.clone()),
+                elapsed,
+                e.billable,
+            ),
+        }
+    }
+
+    if let Grouping::Day | Grouping::Week(_) = grouping {
+        groups.sort_by_key(|g| match g.key {
+            GroupKey::Day(d) | GroupKey::Week(d) => Some(d),
+            _ => None,
+        });
+    }
+    groups
+}
+
+/// All days in `tz` the entry touches. The stop is exclusive, so an entry ending at midnight does not touch the next day.
+fn days<Tz: chrono::TimeZone>(
+    e: &TimeEntry,
+    tz: &Tz,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<chrono::NaiveDate> {
+    let stop = e.effective_stop(now);
+    let last_instant = if stop > e.start {
+        stop - chrono::Duration::nanoseconds(1)
+    } else {
+        e.start
+    };
+    let first = e.start.with_timezone(tz).date_naive();
+    let last = last_instant.with_timezone(tz).date_naive();
+    first.iter_days().take_while(|d| *d <= last).collect()
+}
+
+fn first_day_of_week(day: chrono::NaiveDate, week_start: chrono::Weekday) -> chrono::NaiveDate {
+    let offset = (7 + day.weekday().num_days_from_monday() - week_start.num_days_from_monday()) % 7;
+    day - chrono::Duration::days(i64::from(offset))
+}
+
+/// (De)serializes a duration as whole seconds
+mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &chrono::Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_i64(d.num_seconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<chrono::Duration, D::Error> {
+        i64::deserialize(d).map(chrono::Duration::seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::Workspace;
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc, Weekday};
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn entry(start: DateTime<Utc>, stop: Option<DateTime<Utc>>) -> TimeEntry {
+        TimeEntry {
+            id: 1,
+            guid: uuid::Uuid::nil(),
+            workspace: Rc::new(Workspace {
+                id: 1,
+                name: "Workspace".to_owned(),
+                extra: serde_json::Map::new(),
+            }),
+            project: None,
+            pid: None,
+            tid: None,
+            start,
+            stop,
+            duration: match stop {
+                Some(stop) => (stop - start).num_seconds(),
+                None => -1,
+            },
+            description: None,
+            tags: Vec::new(),
+            billable: false,
+            duronly: false,
+            at: start,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    fn summed(groups: &[Group]) -> Vec<(GroupKey, i64, usize)> {
+        groups
+            .iter()
+            .map(|g| (g.key.clone(), g.totals.total.num_hours(), g.totals.entries))
+            .collect()
+    }
+
+    #[test]
+    fn day_splits_at_midnight() {
+        let e = entry(at(2020, 3, 2, 22), Some(at(2020, 3, 3, 2)));
+        let groups = group_by(&[e], Grouping::Day, &Utc, at(2020, 3, 4, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![
+                (GroupKey::Day(day(2020, 3, 2)), 2, 1),
+                (GroupKey::Day(day(2020, 3, 3)), 2, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn day_entry_ending_at_midnight() {
+        let e = entry(at(2020, 3, 2, 22), Some(at(2020, 3, 3, 0)));
+        let groups = group_by(&[e], Grouping::Day, &Utc, at(2020, 3, 4, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![(GroupKey::Day(day(2020, 3, 2)), 2, 1)]
+        );
+    }
+
+    #[test]
+    fn day_in_other_timezone() {
+        let tz = chrono::FixedOffset::east_opt(3 * 3600).unwrap();
+        // 23:00 to 01:00 in UTC+3
+        let e = entry(at(2020, 3, 2, 20), Some(at(2020, 3, 2, 22)));
+        let groups = group_by(&[e], Grouping::Day, &tz, at(2020, 3, 4, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![
+                (GroupKey::Day(day(2020, 3, 2)), 1, 1),
+                (GroupKey::Day(day(2020, 3, 3)), 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn day_running_entry_until_now() {
+        let e = entry(at(2020, 3, 2, 20), None);
+        let groups = group_by(&[e], Grouping::Day, &Utc, at(2020, 3, 3, 3));
+        assert_eq!(
+            summed(&groups),
+            vec![
+                (GroupKey::Day(day(2020, 3, 2)), 4, 1),
+                (GroupKey::Day(day(2020, 3, 3)), 3, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn week_splits_at_week_start() {
+        // 2020-03-02 is a monday
+        let e = entry(at(2020, 3, 1, 20), Some(at(2020, 3, 2, 1)));
+        let groups = group_by(&[e], Grouping::Week(Weekday::Mon), &Utc, at(2020, 3, 4, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![
+                (GroupKey::Week(day(2020, 2, 24)), 4, 1),
+                (GroupKey::Week(day(2020, 3, 2)), 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn week_entry_ending_at_week_start() {
+        let e = entry(at(2020, 3, 1, 20), Some(at(2020, 3, 2, 0)));
+        let groups = group_by(&[e], Grouping::Week(Weekday::Mon), &Utc, at(2020, 3, 4, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![(GroupKey::Week(day(2020, 2, 24)), 4, 1)]
+        );
+    }
+
+    #[test]
+    fn week_sums_days_of_one_entry() {
+        let e = entry(at(2020, 3, 3, 20), Some(at(2020, 3, 4, 4)));
+        let groups = group_by(&[e], Grouping::Week(Weekday::Mon), &Utc, at(2020, 3, 5, 0));
+        assert_eq!(
+            summed(&groups),
+            vec![(GroupKey::Week(day(2020, 3, 2)), 8, 1)]
+        );
+    }
+}
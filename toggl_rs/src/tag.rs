@@ -0,0 +1,91 @@
+use crate::error::TogglError;
+use crate::types::Return;
+use crate::workspace::Workspace;
+use crate::ApiVersion;
+use crate::Query;
+use crate::Toggl;
+
+/// A tag of a workspace. Entries refer to tags by name.
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    /// Workspace id the tag belongs to
+    #[serde(alias = "workspace_id")]
+    pub wid: i64,
+    pub name: String,
+    /// Fields of the api we do not model, e.g., when the tag was last changed. Renaming only sends the name
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+struct TagWrapper {
+    tag: NewTag,
+}
+
+#[derive(Serialize, Debug)]
+struct NewTag {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wid: Option<i64>,
+}
+
+pub trait TagTrait {
+    /// Loads the tags of the workspace.
+    fn get_tags(&self, w: &Workspace) -> Result<Vec<Tag>, TogglError>;
+
+    /// Creates a tag with the given name in the workspace.
+    fn create_tag(&self, name: &str, w: &Workspace) -> Result<Tag, TogglError>;
+
+    /// Renames the tag, entries with the tag keep it under the new name.
+    fn rename_tag(&self, tag: &Tag, name: &str) -> Result<Tag, TogglError>;
+}
+
+impl TagTrait for Toggl {
+    fn get_tags(&self, w: &Workspace) -> Result<Vec<Tag>, TogglError> {
+        // the api returns null instead of an empty list for workspaces without tags
+        let res: Option<Vec<Tag>> = self.get(&self.url(&format!("/workspaces/{}/tags", w.id)))?;
+        Ok(res.unwrap_or_default())
+    }
+
+    fn create_tag(&self, name: &str, w: &Workspace) -> Result<Tag, TogglError> {
+        match self.api {
+            ApiVersion::V8 => {
+                let t = TagWrapper {
+                    tag: NewTag {
+                        name: name.to_owned(),
+                        wid: Some(w.id),
+                    },
+                };
+                self.post::<&str, TagWrapper, Return<Tag>>(&self.url("/tags"), &t)
+                    .map(|r| r.data)
+            }
+            ApiVersion::V9 => self.post(
+                &self.url(&format!("/workspaces/{}/tags", w.id)),
+                &NewTag {
+                    name: name.to_owned(),
+                    wid: None,
+                },
+            ),
+        }
+    }
+
+    fn rename_tag(&self, tag: &Tag, name: &str) -> Result<Tag, TogglError> {
+        let new = NewTag {
+            name: name.to_owned(),
+            wid: None,
+        };
+        match self.api {
+            ApiVersion::V8 => self
+                .put::<&str, TagWrapper, Return<Tag>>(
+                    &self.url(&format!("/tags/{}", tag.id)),
+                    &TagWrapper { tag: new },
+                )
+                .map(|r| r.data),
+            ApiVersion::V9 => self.put(
+                &self.url(&format!("/workspaces/{}/tags/{}", tag.wid, tag.id)),
+                &new,
+            ),
+        }
+    }
+}
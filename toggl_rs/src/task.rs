@@ -0,0 +1,37 @@
+use crate::error::TogglError;
+use crate::project::Project;
+use crate::ApiVersion;
+use crate::Query;
+use crate::Toggl;
+
+/// A task of a project, entries refer to it by `tid`.
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq, Serialize)]
+pub struct Task {
+    pub id: i64,
+    pub name: String,
+    /// Project id the task belongs to
+    #[serde(alias = "project_id")]
+    pub pid: i64,
+    #[serde(default)]
+    pub active: bool,
+    /// Fields of the api we do not model, e.g., the estimated time of the task
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+pub trait TaskTrait {
+    /// Loads the tasks of the project. Tasks are only available in paid workspaces, otherwise this is empty.
+    fn get_tasks(&self, p: &Project) -> Result<Vec<Task>, TogglError>;
+}
+
+impl TaskTrait for Toggl {
+    fn get_tasks(&self, p: &Project) -> Result<Vec<Task>, TogglError> {
+        let url = match self.api {
+            ApiVersion::V8 => self.url(&format!("/projects/{}/tasks", p.id)),
+            ApiVersion::V9 => self.url(&format!("/workspaces/{}/projects/{}/tasks", p.wid, p.id)),
+        };
+        // like projects, null is returned for projects without tasks
+        let res: Option<Vec<Task>> = self.get(&url)?;
+        Ok(res.unwrap_or_default())
+    }
+}
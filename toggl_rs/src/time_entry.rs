@@ -1,21 +1,168 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
 use crate::error::TogglError;
+use crate::filter::EntryFilter;
 
-use crate::project::Project;
-use crate::types::{DeleteEntryReturn, StartEntryReturn, StopEntryReturn, TimeEntry, TimeEntryRange, TimeEntryReturn, TimeEntryUpdate, TimeEntryRangeSlice};
+use crate::project::{Project, ProjectTrait};
+use crate::types::{DeleteEntryReturn, StartEntryReturn, StopEntryReturn, TimeEntry, TimeEntryRange, TimeEntryReturn, TimeEntryUpdate, TimeEntryRangeSlice, TimeEntryInner};
+use crate::workspace::Workspace;
+use crate::ApiVersion;
 use crate::Query;
 use crate::Toggl;
 
-#[derive(Serialize, Debug)]
-struct StartEntry {
-    time_entry: StartTimeEntry,
+/// The maximum number of entries the api returns for a single request
+const MAX_ENTRIES_PER_REQUEST: usize = 1000;
+/// The size of the windows a range is split into at first
+const WINDOW_DAYS: i64 = 30;
+/// Windows are not split below this size
+const MIN_WINDOW: chrono::Duration = chrono::Duration::minutes(1);
+/// The api does not return entries that start more than three months ago, older entries are only available through the reports api.
+/// Ranges may start at midnight (UTC) this many days ago, which stays within the shortest three months.
+pub const MAX_HISTORY_DAYS: i64 = 88;
+
+/// The earliest start of a range of entries the api returns, see `MAX_HISTORY_DAYS`.
+pub fn history_start() -> chrono::DateTime<chrono::Utc> {
+    (chrono::Utc::now() - chrono::Duration::days(MAX_HISTORY_DAYS))
+        .date_naive()
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+}
+
+/// Optional attributes of new entries, see `TimeEntryExt::start_entry_with`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EntryDetails {
+    /// Workspace of the entry. If None, the workspace of the project or the default workspace of the user is used
+    pub workspace: Option<i64>,
+    /// Task id, tasks belong to the project of the entry
+    pub task: Option<i64>,
+    /// None leaves it to the api, which uses the billable flag of the project
+    pub billable: Option<bool>,
+}
+
+impl EntryDetails {
+    /// The workspace of the entry: the given one, the one of the project or `default_wid`, in this order.
+    pub(crate) fn wid(&self, p: Option<&Project>, default_wid: i64) -> i64 {
+        self.workspace
+            .or_else(|| p.map(|v| v.wid))
+            .unwrap_or(default_wid)
+    }
+
+    /// A new running entry with these details.
+    fn new_entry(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<&Project>,
+        default_wid: i64,
+    ) -> NewEntry {
+        let mut e = NewEntry::new(
+            description,
+            tags,
+            p.map(|v| v.id),
+            self.wid(p, default_wid),
+        );
+        e.tid = self.task;
+        e.billable = self.billable;
+        e
+    }
 }
 
+/// Lazy iterator over the time entries of a range, see `TimeEntryExt::get_time_entries_windows`.
+/// Windows that hit the result limit of the api are split in half until they fit.
+/// If a window of a minute still hits the limit, an `ApiError` is returned instead of a truncated window.
+#[derive(Debug)]
+pub struct TimeEntryWindows<'a> {
+    toggl: &'a Toggl,
+    /// Returned first, e.g., if the range starts before the history limit of the api
+    error: Option<TogglError>,
+    position: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    window: chrono::Duration,
+    seen: HashSet<i64>,
+}
+
+impl<'a> Iterator for TimeEntryWindows<'a> {
+    type Item = Result<Vec<TimeEntry>, TogglError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        while self.position < self.end {
+            let window_end = std::cmp::min(self.position + self.window, self.end);
+            let entries = match self.toggl.query_range(Some(self.position), Some(window_end)) {
+                Ok(v) => v,
+                Err(e) => {
+                    // do not query the failing window forever
+                    self.position = self.end;
+                    return Some(Err(e));
+                }
+            };
+            if entries.len() >= MAX_ENTRIES_PER_REQUEST {
+                if self.window > MIN_WINDOW {
+                    self.window = self.window / 2;
+                    continue;
+                }
+                // the window can not be split further, the result would be truncated
+                let error = TogglError::ApiError(format!(
+                    "Too many entries in a single window starting at {}",
+                    self.position.to_rfc3339()
+                ));
+                self.position = self.end;
+                return Some(Err(error));
+            }
+
+            self.position = window_end;
+            self.window = chrono::Duration::days(WINDOW_DAYS);
+            let seen = &mut self.seen;
+            return Some(Ok(entries
+                .into_iter()
+                .filter(|t| seen.insert(t.id))
+                .collect()));
+        }
+        None
+    }
+}
+
+/// A new entry as it is sent to the api
 #[derive(Serialize, Debug)]
-struct StartTimeEntry {
-    description: Option<String>,
-    tags: Vec<String>,
-    pid: Option<i64>,
-    created_with: String,
+pub(crate) struct NewEntry {
+    /// Client-generated guid, only supported by api v8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guid: Option<uuid::Uuid>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub pid: Option<i64>,
+    pub wid: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tid: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billable: Option<bool>,
+    /// None starts the entry now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<chrono::DateTime<chrono::Utc>>,
+    /// Duration in seconds, None for a running entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<i64>,
+    pub created_with: String,
+}
+
+impl NewEntry {
+    pub(crate) fn new(description: Option<String>, tags: &[String], pid: Option<i64>, wid: i64) -> NewEntry {
+        NewEntry {
+            guid: None,
+            description,
+            tags: tags.to_owned(),
+            pid,
+            wid,
+            tid: None,
+            billable: None,
+            start: None,
+            duration: None,
+            created_with: "toggl-rs".to_string(),
+        }
+    }
 }
 
 /// Main Trait for working with time entries on the toggl struct.
@@ -23,14 +170,47 @@ pub trait TimeEntryExt {
     /// Get all time entries from the api.
     fn get_time_entries(&self) -> Result<Vec<TimeEntry>, TogglError>;
 
-    /// Get all time entries from the specified range (both are optional arguments)
+    /// Get all time entries from the specified range (both are optional arguments).
+    /// If a start is given, the range is queried in windows so that no window hits the result limit of the api.
+    /// Without a start the api returns the entries of the last days only.
+    /// A start before `history_start()` is an `ApiError`, as the api would silently return nothing for it.
     fn get_time_entries_range(
         &self,
         start: Option<chrono::DateTime<chrono::Utc>>,
         end: Option<chrono::DateTime<chrono::Utc>>,
     ) -> Result<Vec<TimeEntry>, TogglError>;
 
+    /// Returns a lazy iterator over the entries between `start` and `end` (or now), one window of entries at a time.
+    /// Every entry is only returned once, even if it appears in several windows.
+    /// If `start` is before `history_start()`, the only item is an `ApiError`.
+    /// A window that can not be split small enough to fit the result limit is an `ApiError` as well and ends the iteration.
+    /// # Example
+    /// ```no_run
+    /// use toggl_rs::{Toggl, TogglExt};
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let t = Toggl::init("api_token")?;
+    ///     let start = chrono::Utc::now() - chrono::Duration::days(80);
+    ///
+    ///     for window in t.get_time_entries_windows(start, None) {
+    ///         for entry in window? {
+    ///             println!("{:?}", entry.description);
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    fn get_time_entries_windows(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> TimeEntryWindows<'_>;
+
+    /// Get all time entries matching the filter. Only the date range is queried on the api, the rest is filtered afterwards.
+    fn get_time_entries_filtered(&self, f: &EntryFilter) -> Result<Vec<TimeEntry>, TogglError>;
+
     /// Starts a time entry with the description, tags and a given project.
+    /// The entry is created in the workspace of the project or, without a project, in the default workspace of the user.
     fn start_entry<T: AsRef<Project>>(
         &self,
         description: Option<String>,
@@ -38,6 +218,51 @@ pub trait TimeEntryExt {
         p: Option<T>,
     ) -> Result<(), TogglError>;
 
+    /// Starts a time entry like `start_entry` but in the given workspace.
+    fn start_entry_in_workspace<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        w: &Workspace,
+    ) -> Result<(), TogglError>;
+
+    /// Starts a time entry like `start_entry` with the workspace, task and billable flag from `details`.
+    fn start_entry_with<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+    ) -> Result<(), TogglError>;
+
+    /// Creates a finished time entry starting at `start` and running for `duration`.
+    /// If no workspace is given, the workspace of the project or the default workspace of the user is used.
+    fn create_entry<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        w: Option<&Workspace>,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Result<(), TogglError>;
+
+    /// Creates a finished time entry like `create_entry` with the workspace, task and billable flag from `details`.
+    fn create_entry_with<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Result<(), TogglError>;
+
+    /// Continues the given entry, i.e., starts a new entry with the same description, project, task, tags, billable flag and workspace.
+    /// The currently running entry is stopped before, if stopping fails no new entry is started.
+    fn continue_entry(&self, t: &TimeEntry) -> Result<(), TogglError>;
+
     /// Stops the supplied time entry. While we technically only look at the id, this is not guaranteed by updates in the api
     fn stop_entry(&self, t: &TimeEntry) -> Result<(), TogglError>;
 
@@ -65,15 +290,48 @@ pub trait TimeEntryExt {
     /// ```
     fn update_entry(&self, t: TimeEntry) -> Result<(), TogglError>;
 
+    /// Updates the entry like `update_entry`, but only if it was not changed on the server since it was fetched.
+    /// Otherwise returns `TogglError::ConflictError` with the local and the server version and does not update anything.
+    fn update_entry_checked(&self, t: TimeEntry) -> Result<(), TogglError>;
+
+    /// Updates only the fields that differ between `original` and `updated`, changes to other fields on the server are kept.
+    /// # Example
+    /// ```no_run
+    /// use toggl_rs::{Toggl, TogglExt};
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let t = Toggl::init("api_token")?;
+    ///
+    ///     let entry = t.get_running_entry()?.unwrap();
+    ///     let mut updated = entry.clone();
+    ///     updated.description = Some("test2".to_string());
+    ///     t.update_entry_fields(&entry, updated)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    fn update_entry_fields(&self, original: &TimeEntry, updated: TimeEntry) -> Result<(), TogglError>;
+
     /// Deletes the entry.
     fn delete_entry(&self, t: &TimeEntry) -> Result<(), TogglError>;
 }
 
 trait TimeEntryTrait {
+    /// Queries the entries in the range with a single request, which may be truncated by the api
+    fn query_range(
+        &self,
+        start: Option<chrono::DateTime<chrono::Utc>>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Vec<TimeEntry>, TogglError>;
+
     /// Converts an array of TimeEntryReturn to Vector of TimeEntry discarding any elements where the data of Return<TimeEntryInner> is None
-    fn convert_response(&self, t: TimeEntryRangeSlice) -> Vec<TimeEntry>;
+    fn convert_response(&self, t: TimeEntryRangeSlice) -> Result<Vec<TimeEntry>, TogglError>;
+
+    fn convert_single(&self, res: &TimeEntryReturn) -> Result<Option<TimeEntry>, TogglError>;
 
-    fn convert_single(&self, res: &TimeEntryReturn) -> Option<TimeEntry>;
+    /// Converts a single entry, fetching its project if it is not loaded.
+    /// Entries of unknown workspaces are an error.
+    fn convert_entry(&self, tjson: &TimeEntryInner) -> Result<TimeEntry, TogglError>;
 }
 
 impl TimeEntryExt for Toggl {
@@ -86,20 +344,46 @@ impl TimeEntryExt for Toggl {
         start: Option<chrono::DateTime<chrono::Utc>>,
         end: Option<chrono::DateTime<chrono::Utc>>,
     ) -> Result<Vec<TimeEntry>, TogglError> {
-        let mut entries = Vec::new();
-        if let Some(s) = start {
-            entries.push(("start_date", s.to_rfc3339()));
-        }
-        if let Some(e) = end {
-            entries.push(("end_date", e.to_rfc3339()));
+        if let Some(start) = start {
+            let mut entries = Vec::new();
+            for window in self.get_time_entries_windows(start, end) {
+                entries.append(&mut window?);
+            }
+            Ok(entries)
+        } else {
+            self.query_range(None, end)
         }
+    }
 
-        let url =
-            reqwest::Url::parse_with_params("https://www.toggl.com/api/v8/time_entries", entries)
-                .expect("Error in parsing URL");
+    fn get_time_entries_windows(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> TimeEntryWindows<'_> {
+        let end = end.unwrap_or_else(chrono::Utc::now);
+        let earliest = history_start();
+        let error = if start < earliest {
+            Some(TogglError::ApiError(format!(
+                "Time entries can only be queried from {} on, older entries are only available in reports",
+                earliest.format("%Y-%m-%d")
+            )))
+        } else {
+            None
+        };
+        TimeEntryWindows {
+            toggl: self,
+            // nothing is queried after the error
+            position: if error.is_some() { end } else { start },
+            error,
+            end,
+            window: chrono::Duration::days(WINDOW_DAYS),
+            seen: HashSet::new(),
+        }
+    }
 
-        let res: TimeEntryRange = self.get(url)?;
-        Ok(self.convert_response(res.as_slice()))
+    fn get_time_entries_filtered(&self, f: &EntryFilter) -> Result<Vec<TimeEntry>, TogglError> {
+        let entries = self.get_time_entries_range(f.start_date(), f.end_date())?;
+        Ok(f.apply(entries, chrono::Utc::now()))
     }
 
     fn start_entry<T: AsRef<Project>>(
@@ -108,73 +392,400 @@ impl TimeEntryExt for Toggl {
         tags: &[String],
         p: Option<T>,
     ) -> Result<(), TogglError> {
-        let t = StartEntry {
-            time_entry: StartTimeEntry {
-                description,
-                tags: tags.to_owned(),
-                pid: p.map(|v| v.as_ref().id),
-                created_with: "toggl-rs".to_string(),
-            },
+        self.start_entry_with(description, tags, p, &EntryDetails::default())
+    }
+
+    fn start_entry_in_workspace<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        w: &Workspace,
+    ) -> Result<(), TogglError> {
+        let details = EntryDetails {
+            workspace: Some(w.id),
+            ..EntryDetails::default()
         };
-        self.post::<&str, StartEntry, StartEntryReturn>(
-            "https://www.toggl.com/api/v8/time_entries/start",
-            &t,
-        )?;
+        self.start_entry_with(description, tags, p, &details)
+    }
+
+    fn start_entry_with<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+    ) -> Result<(), TogglError> {
+        let p = p.as_ref().map(|v| v.as_ref());
+        self.post_entry(&details.new_entry(description, tags, p, self.user.default_wid))?;
+        Ok(())
+    }
+
+    fn create_entry<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        w: Option<&Workspace>,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Result<(), TogglError> {
+        let details = EntryDetails {
+            workspace: w.map(|v| v.id),
+            ..EntryDetails::default()
+        };
+        self.create_entry_with(description, tags, p, &details, start, duration)
+    }
+
+    fn create_entry_with<T: AsRef<Project>>(
+        &self,
+        description: Option<String>,
+        tags: &[String],
+        p: Option<T>,
+        details: &EntryDetails,
+        start: chrono::DateTime<chrono::Utc>,
+        duration: chrono::Duration,
+    ) -> Result<(), TogglError> {
+        let p = p.as_ref().map(|v| v.as_ref());
+        let mut e = details.new_entry(description, tags, p, self.user.default_wid);
+        e.start = Some(start);
+        e.duration = Some(duration.num_seconds());
+        self.post_entry(&e)?;
+        Ok(())
+    }
+
+    fn continue_entry(&self, t: &TimeEntry) -> Result<(), TogglError> {
+        if let Some(running) = self.get_running_entry()? {
+            self.stop_entry(&running)?;
+        }
+        let mut e = NewEntry::new(t.description.clone(), &t.tags, t.project_id(), t.workspace.id);
+        e.tid = t.tid;
+        e.billable = Some(t.billable);
+        self.post_entry(&e)?;
         Ok(())
     }
 
     fn stop_entry(&self, t: &TimeEntry) -> Result<(), TogglError> {
-        self.get::<&str, StopEntryReturn>(&format!(
-            "https://www.toggl.com/api/v8/time_entries/{}/stop",
-            t.id
-        ))?;
+        match self.api {
+            ApiVersion::V8 => {
+                self.get::<&str, StopEntryReturn>(&self.url(&format!("/time_entries/{}/stop", t.id)))?;
+            }
+            ApiVersion::V9 => {
+                self.patch::<&str, serde_json::Map<String, serde_json::Value>, TimeEntryInner>(
+                    &self.url(&format!(
+                        "/workspaces/{}/time_entries/{}/stop",
+                        t.workspace.id, t.id
+                    )),
+                    &serde_json::Map::new(),
+                )?;
+            }
+        }
         Ok(())
     }
 
     fn get_entry_details(&self, id: i64) -> Result<Option<TimeEntry>, TogglError> {
-        self.get::<&str, TimeEntryReturn>(&format!(
-            "https://www.toggl.com/api/v8/time_entries/{}",
-            id
-        ))
-        .map(|r| self.convert_single(&r))
+        match self.api {
+            ApiVersion::V8 => self
+                .get::<&str, TimeEntryReturn>(&self.url(&format!("/time_entries/{}", id)))
+                .and_then(|r| self.convert_single(&r)),
+            ApiVersion::V9 => self
+                .get::<&str, Option<TimeEntryInner>>(&self.url(&format!("/me/time_entries/{}", id)))
+                .and_then(|r| r.map(|t| self.convert_entry(&t)).transpose()),
+        }
     }
 
     fn get_running_entry(&self) -> Result<Option<TimeEntry>, TogglError> {
-        self.get("https://www.toggl.com/api/v8/time_entries/current")
-            .map(|r| self.convert_single(&r))
+        match self.api {
+            ApiVersion::V8 => self
+                .get(&self.url("/time_entries/current"))
+                .and_then(|r| self.convert_single(&r)),
+            ApiVersion::V9 => self
+                .get::<&str, Option<TimeEntryInner>>(&self.url("/me/time_entries/current"))
+                .and_then(|r| r.map(|t| self.convert_entry(&t)).transpose()),
+        }
     }
 
     fn update_entry(&self, t: TimeEntry) -> Result<(), TogglError> {
-        let id = t.id;
-        let entry: TimeEntryUpdate = t.into();
-        self.put::<&str, TimeEntryUpdate, TimeEntryReturn>(
-            &format!("https://www.toggl.com/api/v8/time_entries/{}", id),
-            &entry,
-        )?;
+        self.put_entry(t.into())
+    }
+
+    fn update_entry_checked(&self, t: TimeEntry) -> Result<(), TogglError> {
+        match self.get_entry_details(t.id)? {
+            Some(remote) if remote.at > t.at => Err(TogglError::ConflictError {
+                local: Box::new(t),
+                remote: Box::new(remote),
+            }),
+            Some(_) => self.update_entry(t),
+            None => Err(TogglError::ApiError(format!(
+                "Time entry {} does not exist",
+                t.id
+            ))),
+        }
+    }
+
+    fn update_entry_fields(&self, original: &TimeEntry, updated: TimeEntry) -> Result<(), TogglError> {
+        let id = updated.id;
+        let wid = original.workspace.id;
+        let changed = changed_fields(original, updated)?;
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        match self.api {
+            ApiVersion::V8 => {
+                let mut body = serde_json::Map::new();
+                body.insert("time_entry".to_string(), serde_json::Value::Object(changed));
+                self.put::<&str, serde_json::Map<String, serde_json::Value>, TimeEntryReturn>(
+                    &self.url(&format!("/time_entries/{}", id)),
+                    &body,
+                )?;
+            }
+            ApiVersion::V9 => {
+                self.put::<&str, serde_json::Map<String, serde_json::Value>, TimeEntryInner>(
+                    &self.url(&format!("/workspaces/{}/time_entries/{}", wid, id)),
+                    &v9_fields(changed),
+                )?;
+            }
+        }
         Ok(())
     }
 
     fn delete_entry(&self, t: &TimeEntry) -> Result<(), TogglError> {
-        self.delete::<&str, DeleteEntryReturn>(&format!(
-            "https://www.toggl.com/api/v8/time_entries/{}",
-            t.id
-        ))?;
+        match self.api {
+            ApiVersion::V8 => {
+                self.delete::<&str, DeleteEntryReturn>(&self.url(&format!("/time_entries/{}", t.id)))?;
+            }
+            ApiVersion::V9 => self.delete_empty(&self.url(&format!(
+                "/workspaces/{}/time_entries/{}",
+                t.workspace.id, t.id
+            )))?,
+        }
         Ok(())
     }
 }
 
+impl Toggl {
+    /// Resolves entries in the json format of the api to `TimeEntry` with the loaded projects and workspaces.
+    /// Entries of workspaces that are not loaded are an `ApiError`.
+    pub fn resolve_entries(&self, raw: &[TimeEntryInner]) -> Result<Vec<TimeEntry>, TogglError> {
+        self.convert_response(raw)
+    }
+
+    /// Creates the entry and returns its id.
+    pub(crate) fn post_entry(&self, e: &NewEntry) -> Result<i64, TogglError> {
+        match self.api {
+            ApiVersion::V8 if e.start.is_none() => {
+                let res: StartEntryReturn =
+                    self.post(&self.url("/time_entries/start"), &TimeEntryWrapper { time_entry: e })?;
+                Ok(res.data.id)
+            }
+            ApiVersion::V8 => {
+                let mut body = to_map(e)?;
+                if e.duration.is_none() {
+                    // running entries store the negative start time
+                    let start = e.start.map(|v| v.timestamp()).unwrap_or_default();
+                    body.insert("duration".to_string(), (-start).into());
+                }
+                let res: TimeEntryReturn = self.post(
+                    &self.url("/time_entries"),
+                    &TimeEntryWrapper { time_entry: body },
+                )?;
+                res.data.map(|v| v.id).ok_or_else(|| {
+                    TogglError::ApiError("The api did not return the created entry".to_owned())
+                })
+            }
+            ApiVersion::V9 => {
+                let mut body = to_map(e)?;
+                let start = e.start.unwrap_or_else(chrono::Utc::now);
+                body.insert("start".to_string(), serde_json::to_value(start)?);
+                body.insert("duration".to_string(), e.duration.unwrap_or(-1).into());
+                let res: TimeEntryInner = self.post(
+                    &self.url(&format!("/workspaces/{}/time_entries", e.wid)),
+                    &v9_fields(body),
+                )?;
+                Ok(res.id)
+            }
+        }
+    }
+
+    /// Replaces the entry on the server with all values in `t`.
+    pub(crate) fn put_entry(&self, t: TimeEntryInner) -> Result<(), TogglError> {
+        match self.api {
+            ApiVersion::V8 => {
+                self.put::<&str, TimeEntryUpdate, TimeEntryReturn>(
+                    &self.url(&format!("/time_entries/{}", t.id)),
+                    &TimeEntryUpdate { time_entry: t },
+                )?;
+            }
+            ApiVersion::V9 => {
+                let url = self.url(&format!("/workspaces/{}/time_entries/{}", t.wid, t.id));
+                self.put::<&str, serde_json::Map<String, serde_json::Value>, TimeEntryInner>(
+                    &url,
+                    &v9_fields(to_map(&t)?),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct TimeEntryWrapper<T> {
+    time_entry: T,
+}
+
+fn to_map<T: serde::Serialize>(t: &T) -> Result<serde_json::Map<String, serde_json::Value>, TogglError> {
+    match serde_json::to_value(t)? {
+        serde_json::Value::Object(m) => Ok(m),
+        _ => unreachable!("entries are serialized as objects"),
+    }
+}
+
+/// The fields of the api format that differ between the entries, without `id` and `at`.
+fn changed_fields(
+    original: &TimeEntry,
+    updated: TimeEntry,
+) -> Result<serde_json::Map<String, serde_json::Value>, TogglError> {
+    let original = to_map(&TimeEntryInner::from(original.clone()))?;
+    let updated = to_map(&TimeEntryInner::from(updated))?;
+    Ok(updated
+        .into_iter()
+        .filter(|(k, v)| k != "id" && k != "at" && original.get(k) != Some(v))
+        .collect())
+}
+
+/// Renames the id fields to the names used by api v9 and removes the guid, which v9 does not know.
+fn v9_fields(
+    mut m: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    for (v8, v9) in &[("wid", "workspace_id"), ("pid", "project_id"), ("tid", "task_id")] {
+        if let Some(v) = m.remove(*v8) {
+            m.insert((*v9).to_string(), v);
+        }
+    }
+    m.remove("guid");
+    m
+}
+
 impl TimeEntryTrait for Toggl {
-    fn convert_response(&self, res: TimeEntryRangeSlice) -> Vec<TimeEntry> {
-        res.iter()
-            .map(|tjson| (self.projects.as_ref(), &self.user.workspaces, tjson).into())
-            .collect()
+    fn query_range(
+        &self,
+        start: Option<chrono::DateTime<chrono::Utc>>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Vec<TimeEntry>, TogglError> {
+        let mut entries = Vec::new();
+        if let Some(s) = start {
+            entries.push(("start_date", s.to_rfc3339()));
+        }
+        if let Some(e) = end {
+            entries.push(("end_date", e.to_rfc3339()));
+        }
+
+        let path = match self.api {
+            ApiVersion::V8 => "/time_entries",
+            ApiVersion::V9 => "/me/time_entries",
+        };
+        let url = reqwest::Url::parse_with_params(&self.url(path), entries)
+            .expect("Error in parsing URL");
+
+        let res: TimeEntryRange = self.get(url)?;
+        self.convert_response(res.as_slice())
     }
 
-    fn convert_single(&self, res: &TimeEntryReturn) -> Option<TimeEntry> {
-        if let Some(ref t) = res.data {
-            Some((&self.projects, &self.user.workspaces, t).into())
-        } else {
-            None
+    fn convert_response(&self, res: TimeEntryRangeSlice) -> Result<Vec<TimeEntry>, TogglError> {
+        res.iter().map(|tjson| self.convert_entry(tjson)).collect()
+    }
+
+    fn convert_single(&self, res: &TimeEntryReturn) -> Result<Option<TimeEntry>, TogglError> {
+        res.data.as_ref().map(|t| self.convert_entry(t)).transpose()
+    }
+
+    fn convert_entry(&self, tjson: &TimeEntryInner) -> Result<TimeEntry, TogglError> {
+        let mut t = TimeEntry::try_from((&self.project_index, &self.workspace_index, tjson))?;
+        if t.project.is_none() {
+            t.project = tjson.pid.and_then(|pid| self.get_project(pid));
+        }
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use std::rc::Rc;
+
+    fn entry() -> TimeEntry {
+        let start = Utc.with_ymd_and_hms(2020, 3, 2, 9, 0, 0).unwrap();
+        let mut extra = serde_json::Map::new();
+        extra.insert("user_id".to_owned(), json!(5));
+        TimeEntry {
+            id: 10,
+            guid: uuid::Uuid::new_v4(),
+            workspace: Rc::new(Workspace {
+                id: 1,
+                name: "Work".to_owned(),
+                extra: serde_json::Map::new(),
+            }),
+            project: None,
+            pid: Some(2),
+            tid: Some(3),
+            start,
+            stop: Some(start + chrono::Duration::hours(1)),
+            duration: 3600,
+            description: Some("Old".to_owned()),
+            tags: vec!["a".to_owned()],
+            billable: false,
+            duronly: false,
+            at: start,
+            extra,
+        }
+    }
+
+    #[test]
+    fn only_changed_fields_are_sent() {
+        let original = entry();
+        let mut updated = original.clone();
+        updated.description = Some("New".to_owned());
+        updated.at += chrono::Duration::hours(1);
+        let changed = changed_fields(&original, updated).unwrap();
+        assert_eq!(serde_json::Value::Object(changed), json!({"description": "New"}));
+        assert!(changed_fields(&original, original.clone()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unchanged_extra_fields_are_not_sent() {
+        let original = entry();
+        let mut updated = original.clone();
+        updated.extra.insert("server_deleted_at".to_owned(), json!(null));
+        updated.billable = true;
+        let changed = changed_fields(&original, updated).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(changed),
+            json!({"billable": true, "server_deleted_at": null})
+        );
+    }
+
+    #[test]
+    fn v9_body_uses_v9_names_without_guid() {
+        let original = entry();
+        let mut updated = original.clone();
+        updated.set_project(None);
+        updated.tid = None;
+        updated.guid = uuid::Uuid::new_v4();
+        let changed = changed_fields(&original, updated).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(v9_fields(changed)),
+            json!({"project_id": null, "task_id": null})
+        );
+
+        let all = v9_fields(to_map(&TimeEntryInner::from(original)).unwrap());
+        assert_eq!(all.get("workspace_id"), Some(&json!(1)));
+        assert_eq!(all.get("project_id"), Some(&json!(2)));
+        assert_eq!(all.get("task_id"), Some(&json!(3)));
+        for k in &["wid", "pid", "tid", "guid"] {
+            assert!(!all.contains_key(*k), "{} should not be sent", k);
         }
     }
 }
@@ -1,6 +1,8 @@
+use crate::error::TogglError;
 use crate::project::Project;
 use crate::workspace::Workspace;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 /// The base type for all returned data
@@ -11,18 +13,163 @@ pub struct Return<T> {
 
 /// The Main struct for the timeentry.
 /// Will have a Rc to the project and workspace it belongs to.
-#[derive(Clone, Debug, Eq)]
+///
+/// It serializes to a json object with the fields below, times as RFC 3339 strings
+/// and a snapshot of the workspace and the project (or null) embedded:
+/// ```json
+/// {
+///   "id": 1, "guid": "…", "workspace": {"id": 2, "name": "…"},
+///   "project": {"id": 3, "wid": 2, "cid": null, "name": "…", "billable": false, "active": true},
+///   "pid": 3, "tid": null, "start": "2019-01-01T09:00:00Z", "stop": "2019-01-01T10:00:00Z",
+///   "duration": 3600, "description": "…", "tags": [], "billable": false, "duronly": false,
+///   "at": "2019-01-01T10:00:00Z", "extra": {"uid": 4}
+/// }
+/// ```
+/// `extra` holds fields of the api that are not modelled and is left out if empty.
+/// Deserialized entries own their snapshots, i.e., they do not share the `Rc` with the loaded projects of `Toggl`.
+#[derive(Clone, Debug, Deserialize, Eq, Serialize)]
 pub struct TimeEntry {
     pub id: i64,
     pub guid: uuid::Uuid,
     pub workspace: Rc<Workspace>,
+    /// The resolved project. This is None if the entry has no project or if the project could not be loaded, see `project_state`.
     pub project: Option<Rc<Project>>,
+    /// The project id as returned by the api, this is kept even if the project could not be loaded.
+    /// Use `set_project` to change the project so both stay consistent.
+    pub pid: Option<i64>,
+    /// Task id
+    pub tid: Option<i64>,
     pub start: chrono::DateTime<chrono::Utc>,
     pub stop: Option<chrono::DateTime<chrono::Utc>>,
     pub duration: i64,
     pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub billable: bool,
     pub duronly: bool,
     pub at: chrono::DateTime<chrono::Utc>,
+    /// Fields of the api we do not model, these are sent back on updates
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Whether a time entry has a project and if it could be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProjectState<'a> {
+    /// The entry does not belong to a project
+    NoProject,
+    Loaded(&'a Rc<Project>),
+    /// The entry has a project id but the project is unknown, e.g., because it was deleted or we have no access
+    NotLoaded(i64),
+}
+
+impl TimeEntry {
+    /// Returns whether the entry has a project and if it is loaded.
+    pub fn project_state(&self) -> ProjectState<'_> {
+        match (&self.project, self.pid) {
+            (Some(p), _) => ProjectState::Loaded(p),
+            (None, Some(pid)) => ProjectState::NotLoaded(pid),
+            (None, None) => ProjectState::NoProject,
+        }
+    }
+
+    /// The id of the project of this entry, regardless if the project is loaded.
+    pub fn project_id(&self) -> Option<i64> {
+        self.project.as_ref().map(|p| p.id).or(self.pid)
+    }
+
+    /// Sets (or with None removes) the project of the entry.
+    pub fn set_project(&mut self, p: Option<Rc<Project>>) {
+        self.pid = p.as_ref().map(|v| v.id);
+        self.project = p;
+    }
+
+    /// Returns true if the entry is currently running.
+    /// Api v8 stores the negative start time in epoch seconds as the duration of a running entry, api v9 uses -1.
+    pub fn is_running(&self) -> bool {
+        self.duration < 0
+    }
+
+    /// Moves the start of the entry. A finished entry keeps its stop time, so its duration changes.
+    pub fn set_start(&mut self, start: chrono::DateTime<chrono::Utc>) {
+        if self.is_running() {
+            // keep the convention of the api the entry came from
+            if self.duration != -1 {
+                self.duration = -start.timestamp();
+            }
+        } else {
+            let stop = self
+                .stop
+                .unwrap_or_else(|| self.start + chrono::Duration::seconds(self.duration));
+            self.duration = (stop - start).num_seconds();
+        }
+        self.start = start;
+    }
+
+    /// Sets the stop time and the duration, a running entry is stopped by this.
+    pub fn set_stop(&mut self, stop: chrono::DateTime<chrono::Utc>) {
+        self.stop = Some(stop);
+        self.duration = (stop - self.start).num_seconds();
+    }
+
+    /// The duration of a finished entry or None if the entry is still running.
+    pub fn finished_duration(&self) -> Option<chrono::Duration> {
+        if self.is_running() {
+            None
+        } else {
+            Some(chrono::Duration::seconds(self.duration))
+        }
+    }
+
+    /// The time spent on this entry. For running entries this is the time from the start until `now`.
+    /// This uses the stored duration instead of `stop - start` so it also works for `duronly` entries.
+    pub fn elapsed(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::Duration {
+        self.finished_duration()
+            .unwrap_or_else(|| std::cmp::max(now - self.start, chrono::Duration::zero()))
+    }
+
+    /// The stop time of the entry, or `now` if the entry is still running.
+    pub fn effective_stop(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+        if self.is_running() {
+            now
+        } else {
+            self.stop.unwrap_or_else(|| self.start + self.elapsed(now))
+        }
+    }
+
+    /// The part of the elapsed time that lies between `from` and `to`.
+    pub fn elapsed_between(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::Duration {
+        let start = std::cmp::max(self.start, from);
+        let stop = std::cmp::min(self.effective_stop(now), to);
+        if stop > start {
+            stop - start
+        } else {
+            chrono::Duration::zero()
+        }
+    }
+
+    /// The part of the elapsed time that lies on the given day in the timezone `tz`.
+    /// Entries that cross midnight are split accordingly.
+    pub fn elapsed_on<Tz: chrono::TimeZone>(
+        &self,
+        day: chrono::NaiveDate,
+        tz: &Tz,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::Duration {
+        let midnight = |d: chrono::NaiveDate| {
+            tz.from_local_datetime(&d.and_time(chrono::NaiveTime::MIN))
+                .earliest()
+                .map(|v| v.with_timezone(&chrono::Utc))
+        };
+        match (midnight(day), day.succ_opt().and_then(midnight)) {
+            (Some(from), Some(to)) => self.elapsed_between(from, to, now),
+            _ => chrono::Duration::zero(),
+        }
+    }
 }
 
 impl PartialEq for TimeEntry {
@@ -33,7 +180,7 @@ impl PartialEq for TimeEntry {
 
 impl PartialOrd for TimeEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.start.cmp(&other.start))
+        Some(self.cmp(other))
     }
 }
 
@@ -43,41 +190,54 @@ impl Ord for TimeEntry {
     }
 }
 
-/// Compares if the project id and the possible project id are the same, if `tjsonid` is None, we return false
-fn project_cmp(p: &Project, tjsonid: Option<i64>) -> bool {
-    tjsonid.map(|v| v == p.id).unwrap_or(false)
-}
+/// Resolves the ids of the json entry with lookup tables of projects and workspaces by id.
+/// An entry of a workspace that is not in the table is an `ApiError`, e.g., if the user joined the workspace after it was loaded.
+impl std::convert::TryFrom<(&HashMap<i64, Rc<Project>>, &HashMap<i64, Rc<Workspace>>, &TimeEntryInner)>
+    for TimeEntry
+{
+    type Error = TogglError;
 
-impl From<(&Vec<Rc<Project>>, &Vec<Rc<Workspace>>, &TimeEntryInner)> for TimeEntry {
-    fn from(value: (&Vec<Rc<Project>>, &Vec<Rc<Workspace>>, &TimeEntryInner)) -> TimeEntry {
+    fn try_from(
+        value: (&HashMap<i64, Rc<Project>>, &HashMap<i64, Rc<Workspace>>, &TimeEntryInner),
+    ) -> Result<TimeEntry, TogglError> {
         let p = value.0;
         let w = value.1;
         let tjson = value.2;
         let workspace = w
-            .iter()
-            .find(|ws| ws.id == tjson.wid)
-            .expect("Workspaces was not filled correctly")
+            .get(&tjson.wid)
+            .ok_or_else(|| {
+                TogglError::ApiError(format!(
+                    "Time entry {} belongs to the unknown workspace {}, the loaded workspaces may be outdated",
+                    tjson.id, tjson.wid
+                ))
+            })?
             .clone();
-        let project = p.iter().find(|p| project_cmp(p, tjson.pid)).cloned();
-        TimeEntry {
+        let project = tjson.pid.and_then(|pid| p.get(&pid)).cloned();
+        Ok(TimeEntry {
             id: tjson.id,
             guid: tjson.guid,
             workspace,
             project,
+            pid: tjson.pid,
+            tid: tjson.tid,
             start: tjson.start,
             stop: tjson.stop,
             duration: tjson.duration,
             description: tjson.description.clone(),
+            tags: tjson.tags.clone(),
+            billable: tjson.billable,
             duronly: tjson.duronly,
             at: tjson.at,
-        }
+            extra: tjson.extra.clone(),
+        })
     }
 }
 
 /// The Inner Type for the return from StartEntryCall
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct StartEntryReturnInner {
-    id: i64,
+    pub(crate) id: i64,
     pid: Option<i64>,
     wid: i64,
     billable: bool,
@@ -88,22 +248,43 @@ pub struct StartEntryReturnInner {
 }
 
 /// TimeEntry format that comes from the json api. Notice that it includes ids and not workspace/projects.
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Clone, Deserialize, Debug, Serialize)]
 pub struct TimeEntryInner {
     pub id: i64,
+    /// Api v9 does not return guids, in that case this is the nil uuid
+    #[serde(default)]
     pub guid: uuid::Uuid,
     /// Workspace id
     pub wid: i64,
     /// Project id
     pub pid: Option<i64>,
+    /// Task id
+    pub tid: Option<i64>,
     /// Start time, will be parsed into Utc
     pub start: chrono::DateTime<chrono::Utc>,
     /// End time (optional), will be parsed into Utc
     pub stop: Option<chrono::DateTime<chrono::Utc>>,
     pub duration: i64,
     pub description: Option<String>,
+    /// Tag names, api v8 leaves this out and api v9 sends null if there are no tags
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub billable: bool,
+    #[serde(default)]
     pub duronly: bool,
     pub at: chrono::DateTime<chrono::Utc>,
+    /// All fields we do not model, so newer attributes of the api are not lost on updates
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn null_as_default<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + serde::Deserialize<'de>,
+{
+    <Option<T> as serde::Deserialize>::deserialize(d).map(Option::unwrap_or_default)
 }
 
 pub type StartEntryReturn = Return<StartEntryReturnInner>;
@@ -117,24 +298,34 @@ pub type DeleteEntryReturn = Vec<i64>;
 
 #[derive(Serialize, Debug)]
 pub struct TimeEntryUpdate {
-    time_entry: TimeEntryInner,
+    pub time_entry: TimeEntryInner,
 }
 
 impl From<TimeEntry> for TimeEntryUpdate {
     fn from(t: TimeEntry) -> Self {
         TimeEntryUpdate {
-            time_entry: TimeEntryInner {
-                id: t.id,
-                guid: t.guid,
-                wid: t.workspace.id,
-                pid: t.project.map(|v| v.id),
-                start: t.start,
-                stop: t.stop,
-                duration: t.duration,
-                description: t.description,
-                duronly: t.duronly,
-                at: t.at,
-            },
+            time_entry: t.into(),
+        }
+    }
+}
+
+impl From<TimeEntry> for TimeEntryInner {
+    fn from(t: TimeEntry) -> Self {
+        TimeEntryInner {
+            id: t.id,
+            guid: t.guid,
+            wid: t.workspace.id,
+            pid: t.project_id(),
+            tid: t.tid,
+            start: t.start,
+            stop: t.stop,
+            duration: t.duration,
+            description: t.description,
+            tags: t.tags,
+            billable: t.billable,
+            duronly: t.duronly,
+            at: t.at,
+            extra: t.extra,
         }
     }
 }
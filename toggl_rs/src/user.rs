@@ -2,10 +2,23 @@ use crate::auth::InitResponse;
 use crate::workspace::Workspace;
 use std::rc::Rc;
 
-#[derive(Debug)]
+/// The user with snapshots of its workspaces. Serializes as `{"fullname": "…", "workspaces": [{"id": 1, "name": "…"}], "default_wid": 1}`.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct User {
     pub fullname: String,
     pub workspaces: Vec<Rc<Workspace>>,
+    /// Id of the default workspace as set in the Toggl profile
+    pub default_wid: i64,
+}
+
+impl User {
+    /// Returns the default workspace of the user, if it is one of the workspaces we know about.
+    pub fn default_workspace(&self) -> Option<Rc<Workspace>> {
+        self.workspaces
+            .iter()
+            .find(|w| w.id == self.default_wid)
+            .cloned()
+    }
 }
 
 impl From<InitResponse> for User {
@@ -13,6 +26,7 @@ impl From<InitResponse> for User {
         User {
             fullname: i.data.fullname,
             workspaces: i.data.workspaces.into_iter().map(Rc::new).collect(),
+            default_wid: i.data.default_wid,
         }
     }
 }
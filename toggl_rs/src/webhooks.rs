@@ -0,0 +1,497 @@
+//! Receiving events from Toggl webhook subscriptions, only available with the `webhooks` feature.
+//!
+//! Toggl signs every event with the secret of the subscription, `verify_signature` checks this signature.
+//! `Toggl::parse_event` turns the payload into the same `TimeEntry` and `Project` types the rest of the library uses.
+//! `serve` is a small blocking http server that does both and answers the validation pings of new subscriptions.
+//!
+//! # Example
+//! ```no_run
+//! use toggl_rs::webhooks::{self, Event};
+//! use toggl_rs::Toggl;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let toggl = Toggl::init("token")?;
+//!     webhooks::serve("127.0.0.1:8080", b"secret", &toggl, |event| match event {
+//!         Event::TimeEntry { action, entry } => println!("{:?} {:?}", action, entry.description),
+//!         _ => {}
+//!     })?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Sample payloads can be posted locally with a signature from `sign`, e.g.,
+//! `curl -H "X-Webhook-Signature-256: sha256=..." --data @event.json http://127.0.0.1:8080`.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io;
+
+use crate::error::TogglError;
+use crate::project::Project;
+use crate::types::{TimeEntry, TimeEntryInner};
+use crate::Toggl;
+
+/// The header that contains the signature of the body.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature-256";
+
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+/// Returns the value of the signature header for `body`, e.g., to post sample payloads.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(body);
+    format!(
+        "{}{}",
+        SIGNATURE_PREFIX,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Returns true if `signature` (the value of the signature header) is the signature of `body` with `secret`.
+/// The comparison takes constant time.
+pub fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let expected = match signature
+        .trim()
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(|v| hex::decode(v).ok())
+    {
+        Some(v) => v,
+        None => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// What happened to the object of the event.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Updated,
+    Deleted,
+    /// Actions this library does not know yet
+    #[serde(other)]
+    Other,
+}
+
+/// Describes the object of the event. Toggl sends most ids in here as strings, they are kept in `extra`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Metadata {
+    /// Missing for pings
+    pub action: Option<Action>,
+    /// E.g., `time_entry` or `project`
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An event as it is sent by Toggl.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RawEvent {
+    pub event_id: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub metadata: Metadata,
+    /// The object of the event, or the string `ping` when a subscription is validated
+    pub payload: serde_json::Value,
+    pub validation_code: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An event with its payload resolved.
+#[derive(Debug)]
+pub enum Event {
+    /// Sent when a subscription is created or validated, answer with the `validation_code` to enable it
+    Ping {
+        validation_code: Option<String>,
+    },
+    TimeEntry {
+        action: Action,
+        entry: TimeEntry,
+    },
+    Project {
+        action: Action,
+        project: Project,
+    },
+    /// Events of models this library does not resolve, e.g., clients or tags
+    Other {
+        model: Option<String>,
+        action: Option<Action>,
+        payload: serde_json::Value,
+    },
+}
+
+impl Toggl {
+    /// Parses the body of a webhook request. The signature has to be checked before, see `verify_signature`.
+    /// Projects and workspaces of time entries are resolved like in `resolve_entries`.
+    /// Time entries of workspaces the user did not have when it was loaded are an `ApiError`.
+    pub fn parse_event(&self, body: &[u8]) -> Result<Event, TogglError> {
+        let raw: RawEvent = serde_json::from_slice(body)?;
+        if raw.payload.as_str() == Some("ping") {
+            return Ok(Event::Ping {
+                validation_code: raw.validation_code,
+            });
+        }
+        let action = raw.metadata.action.unwrap_or(Action::Other);
+        match raw.metadata.model.as_deref() {
+            Some("time_entry") => {
+                let inner: TimeEntryInner = serde_json::from_value(legacy_ids(raw.payload))?;
+                let entry = self
+                    .resolve_entries(std::slice::from_ref(&inner))?
+                    .pop()
+                    .expect("one entry is resolved to one entry");
+                Ok(Event::TimeEntry { action, entry })
+            }
+            Some("project") => Ok(Event::Project {
+                action,
+                project: serde_json::from_value(legacy_ids(raw.payload))?,
+            }),
+            _ => Ok(Event::Other {
+                model: raw.metadata.model,
+                action: raw.metadata.action,
+                payload: raw.payload,
+            }),
+        }
+    }
+}
+
+/// Webhook payloads use the names of api v9, copies them to the v8 names if those are missing.
+fn legacy_ids(mut v: serde_json::Value) -> serde_json::Value {
+    if let Some(m) = v.as_object_mut() {
+        for (v8, v9) in &[
+            ("wid", "workspace_id"),
+            ("pid", "project_id"),
+            ("tid", "task_id"),
+            ("cid", "client_id"),
+        ] {
+            if !m.contains_key(*v8) {
+                if let Some(id) = m.get(*v9).cloned() {
+                    m.insert((*v8).to_string(), id);
+                }
+            }
+        }
+    }
+    v
+}
+
+/// Handles a single request: checks the signature, answers pings and returns all other events.
+/// Invalid requests are answered with an error status and return None.
+pub fn handle(
+    secret: &[u8],
+    t: &Toggl,
+    mut request: tiny_http::Request,
+) -> Result<Option<Event>, TogglError> {
+    if *request.method() != tiny_http::Method::Post {
+        request.respond(tiny_http::Response::empty(405))?;
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    request.as_reader().read_to_end(&mut body)?;
+    let signature = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(SIGNATURE_HEADER))
+        .map(|h| h.value.as_str().to_owned());
+    if !signature.is_some_and(|s| verify_signature(secret, &body, &s)) {
+        request.respond(tiny_http::Response::empty(401))?;
+        return Ok(None);
+    }
+    match t.parse_event(&body) {
+        Ok(Event::Ping { validation_code }) => {
+            let answer = serde_json::json!({ "validation_code": validation_code });
+            let header =
+                tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                    .expect("static header is valid");
+            request.respond(
+                tiny_http::Response::from_string(answer.to_string()).with_header(header),
+            )?;
+            Ok(None)
+        }
+        Ok(e) => {
+            request.respond(tiny_http::Response::empty(200))?;
+            Ok(Some(e))
+        }
+        Err(_) => {
+            request.respond(tiny_http::Response::empty(400))?;
+            Ok(None)
+        }
+    }
+}
+
+/// Listens on `addr` and calls `f` with every valid event. Blocks forever unless the server can not be started.
+pub fn serve<F: FnMut(Event)>(
+    addr: &str,
+    secret: &[u8],
+    t: &Toggl,
+    mut f: F,
+) -> Result<(), TogglError> {
+    let server = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+    for request in server.incoming_requests() {
+        // errors only concern this request, e.g., the client hung up before the answer was sent
+        if let Ok(Some(e)) = handle(secret, t, request) {
+            f(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4231, test case 2
+    const SECRET: &[u8] = b"Jefe";
+    const BODY: &[u8] = b"what do ya want for nothing?";
+    const SIGNATURE: &str =
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    #[test]
+    fn sign_known_vector() {
+        assert_eq!(sign(SECRET, BODY), SIGNATURE);
+    }
+
+    #[test]
+    fn verify_known_vector() {
+        assert!(verify_signature(SECRET, BODY, SIGNATURE));
+        assert!(verify_signature(SECRET, BODY, &format!(" {}\n", SIGNATURE)));
+    }
+
+    #[test]
+    fn verify_rejects_wrong_secret_or_body() {
+        assert!(!verify_signature(b"other", BODY, SIGNATURE));
+        assert!(!verify_signature(
+            SECRET,
+            b"what do ya want for something?",
+            SIGNATURE
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_missing_prefix() {
+        let bare = SIGNATURE.trim_start_matches(SIGNATURE_PREFIX);
+        assert!(!verify_signature(SECRET, BODY, bare));
+        assert!(!verify_signature(SECRET, BODY, &format!("sha1={}", bare)));
+    }
+
+    #[test]
+    fn verify_rejects_bad_hex() {
+        assert!(!verify_signature(SECRET, BODY, "sha256=xyz"));
+        assert!(!verify_signature(SECRET, BODY, "sha256=5bd"));
+        assert!(!verify_signature(SECRET, BODY, "sha256="));
+        // a truncated but otherwise valid signature
+        assert!(!verify_signature(
+            SECRET,
+            BODY,
+            &SIGNATURE[..SIGNATURE.len() - 2]
+        ));
+    }
+
+    fn toggl() -> Toggl {
+        let user = serde_json::from_value(serde_json::json!({
+            "fullname": "Test", "workspaces": [{"id": 1, "name": "Work"}], "default_wid": 1
+        }))
+        .unwrap();
+        let project = serde_json::from_value(serde_json::json!({
+            "id": 2, "wid": 1, "cid": null, "name": "Project", "billable": true, "active": true
+        }))
+        .unwrap();
+        Toggl::from_parts("token", user, vec![project])
+    }
+
+    fn event(action: &str, model: &str, payload: serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "event_id": 1,
+            "created_at": "2020-03-02T10:00:00Z",
+            "metadata": {"action": action, "model": model, "workspace_id": "1"},
+            "payload": payload,
+            "validation_code": null
+        }))
+        .unwrap()
+    }
+
+    fn entry_payload(workspace_id: i64) -> serde_json::Value {
+        serde_json::json!({
+            "id": 10,
+            "workspace_id": workspace_id,
+            "project_id": 2,
+            "task_id": null,
+            "start": "2020-03-02T09:00:00Z",
+            "stop": "2020-03-02T10:00:00Z",
+            "duration": 3600,
+            "description": "Work",
+            "tags": ["a"],
+            "billable": true,
+            "at": "2020-03-02T10:00:00Z",
+            "user_id": 5
+        })
+    }
+
+    #[test]
+    fn parse_time_entry_created() {
+        let t = toggl();
+        match t.parse_event(&event("created", "time_entry", entry_payload(1))) {
+            Ok(Event::TimeEntry { action, entry }) => {
+                assert_eq!(action, Action::Created);
+                assert_eq!(entry.id, 10);
+                assert_eq!(entry.workspace.id, 1);
+                assert_eq!(
+                    entry.project.as_ref().map(|p| p.name.as_str()),
+                    Some("Project")
+                );
+                assert_eq!(entry.pid, Some(2));
+                assert_eq!(entry.tid, None);
+                assert_eq!(entry.tags, vec!["a".to_owned()]);
+                assert_eq!(entry.duration, 3600);
+                assert_eq!(entry.extra.get("user_id"), Some(&serde_json::json!(5)));
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_time_entry_updated() {
+        let t = toggl();
+        let mut payload = entry_payload(1);
+        payload["description"] = serde_json::json!("Changed");
+        match t.parse_event(&event("updated", "time_entry", payload)) {
+            Ok(Event::TimeEntry { action, entry }) => {
+                assert_eq!(action, Action::Updated);
+                assert_eq!(entry.description.as_deref(), Some("Changed"));
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_time_entry_of_unknown_workspace_is_an_error() {
+        let t = toggl();
+        match t.parse_event(&event("created", "time_entry", entry_payload(99))) {
+            Err(TogglError::ApiError(m)) => assert!(m.contains("99"), "{}", m),
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_project_event() {
+        let t = toggl();
+        let payload = serde_json::json!({
+            "id": 3, "workspace_id": 1, "client_id": 4, "name": "New", "billable": false, "active": true
+        });
+        match t.parse_event(&event("deleted", "project", payload)) {
+            Ok(Event::Project { action, project }) => {
+                assert_eq!(action, Action::Deleted);
+                assert_eq!(project.id, 3);
+                assert_eq!(project.wid, 1);
+                assert_eq!(project.cid, Some(4));
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_other_event() {
+        let t = toggl();
+        let payload = serde_json::json!({"id": 4, "name": "Client"});
+        match t.parse_event(&event("archived", "client", payload.clone())) {
+            Ok(Event::Other {
+                model,
+                action,
+                payload: p,
+            }) => {
+                assert_eq!(model.as_deref(), Some("client"));
+                assert_eq!(action, Some(Action::Other));
+                assert_eq!(p, payload);
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_ping() {
+        let t = toggl();
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event_id": 0,
+            "created_at": "2020-03-02T10:00:00Z",
+            "metadata": {"request_type": "POST"},
+            "payload": "ping",
+            "validation_code": "abc"
+        }))
+        .unwrap();
+        match t.parse_event(&body) {
+            Ok(Event::Ping { validation_code }) => {
+                assert_eq!(validation_code.as_deref(), Some("abc"))
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn legacy_ids_keep_existing_v8_names() {
+        let v = legacy_ids(serde_json::json!({"wid": 1, "workspace_id": 2, "project_id": 3}));
+        assert_eq!(v["wid"], 1);
+        assert_eq!(v["pid"], 3);
+        assert!(v.get("tid").is_none());
+    }
+
+    /// Posts `body` to a server on a free port and lets `handle` answer it.
+    fn post(body: Vec<u8>, signature: Option<String>) -> (Option<Event>, u16, String) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let client = std::thread::spawn(move || {
+            let mut request = reqwest::Client::new().post(&url).body(body);
+            if let Some(s) = signature {
+                request = request.header(SIGNATURE_HEADER, s);
+            }
+            let mut response = request.send().unwrap();
+            (response.status().as_u16(), response.text().unwrap())
+        });
+        let event = handle(SECRET, &toggl(), server.recv().unwrap()).unwrap();
+        let (status, text) = client.join().unwrap();
+        (event, status, text)
+    }
+
+    #[test]
+    fn handle_answers_ping() {
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event_id": 0,
+            "created_at": "2020-03-02T10:00:00Z",
+            "metadata": {},
+            "payload": "ping",
+            "validation_code": "abc"
+        }))
+        .unwrap();
+        let signature = sign(SECRET, &body);
+        let (event, status, text) = post(body, Some(signature));
+        assert!(event.is_none());
+        assert_eq!(status, 200);
+        assert_eq!(text, r#"{"validation_code":"abc"}"#);
+    }
+
+    #[test]
+    fn handle_returns_signed_events() {
+        let body = event("created", "time_entry", entry_payload(1));
+        let signature = sign(SECRET, &body);
+        let (event, status, _) = post(body, Some(signature));
+        assert!(matches!(event, Some(Event::TimeEntry { .. })));
+        assert_eq!(status, 200);
+    }
+
+    #[test]
+    fn handle_rejects_unsigned_and_unknown_events() {
+        let body = event("created", "time_entry", entry_payload(1));
+        let (parsed, status, _) = post(body.clone(), None);
+        assert!(parsed.is_none());
+        assert_eq!(status, 401);
+
+        let (parsed, status, _) = post(body, Some(sign(b"other", BODY)));
+        assert!(parsed.is_none());
+        assert_eq!(status, 401);
+
+        let body = event("created", "time_entry", entry_payload(99));
+        let signature = sign(SECRET, &body);
+        let (parsed, status, _) = post(body, Some(signature));
+        assert!(parsed.is_none());
+        assert_eq!(status, 400);
+    }
+}
@@ -1,6 +1,9 @@
-#[derive(Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 /// Main Struct to store workspaces.
 pub struct Workspace {
     pub id: i64,
     pub name: String,
+    /// All fields we do not model, so newer attributes of the api are not lost
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }